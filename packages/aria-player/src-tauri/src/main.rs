@@ -59,6 +59,29 @@ fn main() {
             let path = PathBuf::from(".app-config");
             let store = app.store(path).unwrap();
             utils::set_config_if_null(&store, "minimizetotray", || json!(false));
+            utils::set_config_if_null(&store, "audioextensions", || {
+                json!(plugins::tauri_player::scanner::DEFAULT_AUDIO_EXTENSIONS)
+            });
+            utils::set_config_if_null(&store, "detectunknownaudiofiles", || json!(false));
+            utils::set_config_if_null(&store, "skiphiddenfolders", || json!(true));
+            utils::set_config_if_null(&store, "excludepatterns", || {
+                json!(plugins::tauri_player::scanner::DEFAULT_EXCLUDE_PATTERNS)
+            });
+            utils::set_config_if_null(&store, "libraryexcludes", || json!({}));
+            utils::set_config_if_null(&store, "splitcuesheets", || json!(true));
+            utils::set_config_if_null(&store, "artworkcachelimit", || {
+                json!(plugins::tauri_player::artwork::DEFAULT_ARTWORK_CACHE_LIMIT_MB)
+            });
+            utils::set_config_if_null(&store, "folderartworkpatterns", || {
+                json!(plugins::tauri_player::artwork::DEFAULT_FOLDER_ARTWORK_PATTERNS)
+            });
+            utils::set_config_if_null(&store, "playbackoutput", || json!("device"));
+            utils::set_config_if_null(&store, "crossfadeduration", || json!(0));
+            utils::set_config_if_null(&store, "crossfadesamealbum", || json!(false));
+            utils::set_config_if_null(&store, "fadeduration", || json!(50));
+            utils::set_config_if_null(&store, "eqpresets", || {
+                plugins::tauri_player::dsp::default_eq_presets()
+            });
             store.save().unwrap();
 
             let language_code = utils::get_language(&app.app_handle());
@@ -122,6 +145,18 @@ fn main() {
         .manage(Mutex::new(
             crate::plugins::discord_rich_presence::DiscordWorker::new(),
         ))
+        .manage(Mutex::new(
+            crate::plugins::tauri_player::watcher::LibraryWatcher::new(),
+        ))
+        .manage(Mutex::new(
+            crate::plugins::tauri_player::metadata_batch::MetadataBatches::new(),
+        ))
+        .manage(Mutex::new(
+            crate::plugins::tauri_player::artwork::ArtworkCache::new(),
+        ))
+        .manage(Mutex::new(
+            crate::plugins::tauri_player::playback::PlaybackEngine::new(),
+        ))
         .invoke_handler(tauri::generate_handler![
             commands::greet,
             commands::ready,
@@ -134,8 +169,41 @@ fn main() {
             commands::set_initial_language,
             oauth::start_server,
             crate::plugins::tauri_player::get_audio_files_from_directory,
+            crate::plugins::tauri_player::scanner::scan_directory_streamed,
             crate::plugins::tauri_player::get_metadata,
+            crate::plugins::tauri_player::metadata_batch::get_metadata_batch,
+            crate::plugins::tauri_player::metadata_batch::cancel_metadata_batch,
+            crate::plugins::tauri_player::tag_editor::set_metadata,
+            crate::plugins::tauri_player::tag_journal::set_metadata_batch,
+            crate::plugins::tauri_player::tag_journal::undo_metadata_batch,
+            crate::plugins::tauri_player::tag_journal::get_metadata_journals,
             crate::plugins::tauri_player::show_file_in_manager,
+            crate::plugins::tauri_player::artwork::get_artwork_thumbnail,
+            crate::plugins::tauri_player::artwork::prune_artwork_cache,
+            crate::plugins::tauri_player::loudness::analyze_loudness,
+            crate::plugins::tauri_player::lyrics::get_lyrics,
+            crate::plugins::tauri_player::cue_sheet::get_cue_track,
+            crate::plugins::tauri_player::playlist::import_playlist,
+            crate::plugins::tauri_player::playlist::export_playlist,
+            crate::plugins::tauri_player::playback::load_playback_track,
+            crate::plugins::tauri_player::playback::set_next_playback_track,
+            crate::plugins::tauri_player::playback::resume_playback,
+            crate::plugins::tauri_player::playback::pause_playback,
+            crate::plugins::tauri_player::playback::seek_playback,
+            crate::plugins::tauri_player::playback::set_playback_volume,
+            crate::plugins::tauri_player::playback::stop_playback,
+            crate::plugins::tauri_player::dsp::get_dsp_settings,
+            crate::plugins::tauri_player::dsp::set_dsp_settings,
+            crate::plugins::tauri_player::dsp::get_eq_presets,
+            crate::plugins::tauri_player::dsp::save_eq_preset,
+            crate::plugins::tauri_player::dsp::delete_eq_preset,
+            crate::plugins::tauri_player::dsp::render_dsp,
+            crate::plugins::tauri_player::normalization::get_normalization_settings,
+            crate::plugins::tauri_player::normalization::set_normalization_settings,
+            crate::plugins::tauri_player::scan_index::get_library_changes,
+            crate::plugins::tauri_player::scan_index::clear_scan_index,
+            crate::plugins::tauri_player::watcher::watch_library_folder,
+            crate::plugins::tauri_player::watcher::unwatch_library_folder,
             crate::plugins::apple_music_player::open_auth_window,
             crate::plugins::apple_music_player::close_auth_window,
             crate::plugins::apple_music_player::post_message_to_auth_window,
@@ -0,0 +1,450 @@
+use super::sha256_hex;
+use super::tag_editor::{get_temp_path, TEMP_FILE_SUFFIX};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use image::codecs::jpeg::JpegEncoder;
+use image::ImageFormat;
+use lofty::picture::{Picture, PictureType};
+use lofty::tag::Tag;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+use tauri::{AppHandle, Manager, State};
+use tauri_plugin_store::StoreExt;
+
+/// Thumbnails are generated to fit within a square of each of these sizes, in pixels.
+pub const THUMBNAIL_SIZES: [u32; 3] = [64, 256, 512];
+const THUMBNAIL_JPEG_QUALITY: u8 = 85;
+/// Limit on the total size of the artwork cache in megabytes, where 0 means unlimited.
+pub const DEFAULT_ARTWORK_CACHE_LIMIT_MB: u64 = 1024;
+/// Image files next to a track that are used as its artwork when none is embedded,
+/// in order of preference. Matched case-insensitively against file names.
+pub const DEFAULT_FOLDER_ARTWORK_PATTERNS: &[&str] = &[
+    "cover.*",
+    "folder.*",
+    "front.*",
+    "album.*",
+    "albumart.*",
+    "albumartlarge.*",
+];
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ArtworkInfo {
+    pub hash: String,
+    pub picture_type: String,
+    pub mime_type: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArtworkPruneResult {
+    pub removed_count: usize,
+    pub freed_bytes: u64,
+}
+
+/// A cached image together with its thumbnails.
+struct CacheEntry {
+    hash: String,
+    size: u64,
+    last_used: SystemTime,
+}
+
+/// Keeps track of the artwork cache's total size, so the size limit can be
+/// enforced without listing the whole cache every time an image is added.
+pub struct ArtworkCache {
+    size: Option<u64>,
+    /// Hashes of folder artwork by path and modified date, since every track
+    /// in an album would otherwise read and hash the same image.
+    folder_artwork: HashMap<PathBuf, (SystemTime, String)>,
+}
+
+impl ArtworkCache {
+    pub fn new() -> Self {
+        Self {
+            size: None,
+            folder_artwork: HashMap::new(),
+        }
+    }
+
+    /// Accounts for `added_bytes` of new files and evicts the least recently used
+    /// images if the cache is now over its limit. The image `keep` is never evicted.
+    fn grow(&mut self, cache_dir: &Path, added_bytes: u64, keep: &str, limit: u64) {
+        let size = match self.size {
+            Some(size) => size + added_bytes,
+            None => list_cache_entries(cache_dir)
+                .iter()
+                .map(|entry| entry.size)
+                .sum(),
+        };
+        self.size = Some(size);
+        if limit > 0 && size > limit {
+            self.evict(cache_dir, keep, limit);
+        }
+    }
+
+    fn evict(&mut self, cache_dir: &Path, keep: &str, limit: u64) {
+        let mut entries = list_cache_entries(cache_dir);
+        entries.sort_by_key(|entry| entry.last_used);
+        let mut size: u64 = entries.iter().map(|entry| entry.size).sum();
+        for entry in entries {
+            if size <= limit {
+                break;
+            }
+            if entry.hash != keep && remove_cache_entry(cache_dir, &entry.hash) {
+                size = size.saturating_sub(entry.size);
+            }
+        }
+        self.size = Some(size);
+    }
+}
+
+type ArtworkCacheState<'a> = State<'a, Mutex<ArtworkCache>>;
+
+pub fn get_artwork_cache_dir(app: &AppHandle) -> Result<PathBuf, String> {
+    match app.path().app_data_dir() {
+        Ok(path) => Ok(path.join(".artwork-cache")),
+        Err(_) => Err("Couldn't get app data directory".to_string()),
+    }
+}
+
+fn get_cache_limit(app: &AppHandle) -> u64 {
+    let store = app.store(PathBuf::from(".app-config")).unwrap();
+    let limit_mb = store
+        .get("artworkcachelimit")
+        .and_then(|val| val.as_u64())
+        .unwrap_or(DEFAULT_ARTWORK_CACHE_LIMIT_MB);
+    limit_mb * 1024 * 1024
+}
+
+fn is_valid_hash(hash: &str) -> bool {
+    hash.len() == 64 && hash.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+fn get_thumbnail_path(cache_dir: &Path, hash: &str, size: u32) -> PathBuf {
+    cache_dir
+        .join("thumbnails")
+        .join(size.to_string())
+        .join(hash)
+}
+
+fn get_file_size(path: &Path) -> u64 {
+    fs::metadata(path).map(|meta| meta.len()).unwrap_or(0)
+}
+
+fn get_thumbnails_size(cache_dir: &Path, hash: &str) -> u64 {
+    THUMBNAIL_SIZES
+        .iter()
+        .map(|&size| get_file_size(&get_thumbnail_path(cache_dir, hash, size)))
+        .sum()
+}
+
+fn list_cache_entries(cache_dir: &Path) -> Vec<CacheEntry> {
+    let Ok(read_dir) = fs::read_dir(cache_dir) else {
+        return Vec::new();
+    };
+    read_dir
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let hash = entry.file_name().to_string_lossy().to_string();
+            if !is_valid_hash(&hash) {
+                return None;
+            }
+            let meta = entry.metadata().ok()?;
+            Some(CacheEntry {
+                size: meta.len() + get_thumbnails_size(cache_dir, &hash),
+                last_used: meta.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+                hash,
+            })
+        })
+        .collect()
+}
+
+/// Removes an image and its thumbnails, returning whether the image itself was removed.
+fn remove_cache_entry(cache_dir: &Path, hash: &str) -> bool {
+    for size in THUMBNAIL_SIZES {
+        let _ = fs::remove_file(get_thumbnail_path(cache_dir, hash, size));
+    }
+    fs::remove_file(cache_dir.join(hash)).is_ok()
+}
+
+/// Writes to a temporary file first, so a crash can't leave a truncated image in the cache.
+fn write_atomically(path: &Path, data: &[u8]) -> Result<(), String> {
+    let temp_path = get_temp_path(path)?;
+    let result = fs::write(&temp_path, data)
+        .and_then(|_| fs::rename(&temp_path, path))
+        .map_err(|e| e.to_string());
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+/// The cache is evicted in order of modified date, so it's bumped whenever an image is used.
+fn mark_used(path: &Path) {
+    let _ = fs::File::options()
+        .write(true)
+        .open(path)
+        .and_then(|file| file.set_modified(SystemTime::now()));
+}
+
+/// Reads an image from the artwork cache, removing it if its contents no longer match its hash.
+pub fn read_cached_artwork(app: &AppHandle, hash: &str) -> Result<Vec<u8>, String> {
+    let cache_dir = get_artwork_cache_dir(app)?;
+    let artwork_path = cache_dir.join(hash);
+    let data = fs::read(&artwork_path).map_err(|e| e.to_string())?;
+    if sha256_hex(&data) != hash {
+        remove_cache_entry(&cache_dir, hash);
+        return Err("Cached artwork is corrupt".to_string());
+    }
+    mark_used(&artwork_path);
+    Ok(data)
+}
+
+/// Writes a JPEG thumbnail of `data` for every size in `THUMBNAIL_SIZES`.
+/// Images that already fit within a size are re-encoded without being scaled up.
+fn create_thumbnails(cache_dir: &Path, hash: &str, data: &[u8]) -> Result<(), String> {
+    let image = image::load_from_memory(data).map_err(|e| e.to_string())?;
+    for size in THUMBNAIL_SIZES {
+        let thumbnail = if image.width() <= size && image.height() <= size {
+            image.to_rgb8()
+        } else {
+            image.thumbnail(size, size).to_rgb8()
+        };
+        let mut encoded = Vec::new();
+        thumbnail
+            .write_with_encoder(JpegEncoder::new_with_quality(
+                &mut encoded,
+                THUMBNAIL_JPEG_QUALITY,
+            ))
+            .map_err(|e| e.to_string())?;
+        let thumbnail_path = get_thumbnail_path(cache_dir, hash, size);
+        if let Some(parent) = thumbnail_path.parent() {
+            fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        write_atomically(&thumbnail_path, &encoded)?;
+    }
+    Ok(())
+}
+
+/// Stores image data in the artwork cache under its SHA-256 hash and returns the hash.
+pub fn cache_artwork(app: &AppHandle, data: &[u8]) -> Result<String, String> {
+    let hash = sha256_hex(data);
+    let cache_dir = get_artwork_cache_dir(app)?;
+    let artwork_path = cache_dir.join(&hash);
+    // Held until the cache's size is updated, so workers caching the same cover at
+    // the same time don't both write it and count it twice
+    let state = app.state::<Mutex<ArtworkCache>>();
+    let mut cache = state.lock().unwrap();
+    // Images are written atomically, so one with the right size is complete
+    if fs::metadata(&artwork_path).is_ok_and(|meta| meta.len() == data.len() as u64) {
+        mark_used(&artwork_path);
+        return Ok(hash);
+    }
+    let _ = fs::create_dir_all(&cache_dir);
+    if write_atomically(&artwork_path, data).is_ok() {
+        // Not every image format can be decoded, and those are still usable at full size
+        let _ = create_thumbnails(&cache_dir, &hash, data);
+        let added_bytes = data.len() as u64 + get_thumbnails_size(&cache_dir, &hash);
+        cache.grow(&cache_dir, added_bytes, &hash, get_cache_limit(app));
+    }
+    Ok(hash)
+}
+
+fn cache_picture(app: &AppHandle, picture: &Picture) -> Result<ArtworkInfo, String> {
+    Ok(ArtworkInfo {
+        hash: cache_artwork(app, picture.data())?,
+        picture_type: format!("{:?}", picture.pic_type()),
+        mime_type: picture.mime_type().map(|mime| mime.as_str().to_string()),
+    })
+}
+
+/// Caches every picture embedded in `tag`, with the front cover first if there is one.
+pub fn cache_embedded_artwork(app: &AppHandle, tag: &Tag) -> Result<Vec<ArtworkInfo>, String> {
+    let mut pictures: Vec<&Picture> = tag.pictures().iter().collect();
+    // Stable sort, so the remaining pictures keep the order they were stored in
+    pictures.sort_by_key(|picture| picture.pic_type() != PictureType::CoverFront);
+    pictures
+        .into_iter()
+        .map(|picture| cache_picture(app, picture))
+        .collect()
+}
+
+fn get_folder_artwork_patterns(app: &AppHandle) -> Vec<String> {
+    let store = app.store(PathBuf::from(".app-config")).unwrap();
+    store
+        .get("folderartworkpatterns")
+        .and_then(|val| serde_json::from_value(val).ok())
+        .unwrap_or_else(|| {
+            DEFAULT_FOLDER_ARTWORK_PATTERNS
+                .iter()
+                .map(|pattern| pattern.to_string())
+                .collect()
+        })
+}
+
+fn build_glob_set(patterns: &[String]) -> GlobSet {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        match GlobBuilder::new(pattern).case_insensitive(true).build() {
+            Ok(glob) => {
+                builder.add(glob);
+            }
+            Err(e) => eprintln!("Invalid folder artwork pattern {}: {}", pattern, e),
+        }
+    }
+    builder.build().unwrap_or_else(|_| GlobSet::empty())
+}
+
+/// Finds the image in the track's directory matching the earliest folder artwork pattern.
+fn find_folder_artwork(app: &AppHandle, track_path: &Path) -> Option<PathBuf> {
+    let directory = track_path.parent()?;
+    let glob_set = build_glob_set(&get_folder_artwork_patterns(app));
+    fs::read_dir(directory)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && ImageFormat::from_path(path).is_ok())
+        .filter_map(|path| {
+            let file_name = path.file_name()?;
+            // The first matching pattern is the most preferred one
+            let pattern_index = glob_set.matches(file_name).into_iter().min()?;
+            Some((pattern_index, path))
+        })
+        .min()
+        .map(|(_, path)| path)
+}
+
+/// Caches the image next to `track_path` that matches the folder artwork patterns,
+/// for tracks without embedded artwork. Returns an empty list if there is none.
+pub fn cache_folder_artwork(
+    app: &AppHandle,
+    track_path: &Path,
+) -> Result<Vec<ArtworkInfo>, String> {
+    let Some(image_path) = find_folder_artwork(app, track_path) else {
+        return Ok(Vec::new());
+    };
+    let cache_dir = get_artwork_cache_dir(app)?;
+    let date_modified = fs::metadata(&image_path)
+        .and_then(|meta| meta.modified())
+        .map_err(|e| e.to_string())?;
+    let state = app.state::<Mutex<ArtworkCache>>();
+    let known_hash = state
+        .lock()
+        .unwrap()
+        .folder_artwork
+        .get(&image_path)
+        .filter(|(modified, hash)| *modified == date_modified && cache_dir.join(hash).exists())
+        .map(|(_, hash)| hash.clone());
+    let hash = match known_hash {
+        Some(hash) => hash,
+        None => {
+            let data = fs::read(&image_path).map_err(|e| e.to_string())?;
+            let hash = cache_artwork(app, &data)?;
+            state
+                .lock()
+                .unwrap()
+                .folder_artwork
+                .insert(image_path.clone(), (date_modified, hash.clone()));
+            hash
+        }
+    };
+    Ok(vec![ArtworkInfo {
+        hash,
+        picture_type: format!("{:?}", PictureType::CoverFront),
+        mime_type: ImageFormat::from_path(&image_path)
+            .ok()
+            .map(|format| format.to_mime_type().to_string()),
+    }])
+}
+
+/// Returns the path of the smallest thumbnail of at least `size` pixels, or of the original
+/// image if `size` is larger than every thumbnail. Missing thumbnails are generated from the
+/// cached original, so artwork cached before thumbnails existed still gets them.
+#[tauri::command]
+pub fn get_artwork_thumbnail(
+    app: AppHandle,
+    state: ArtworkCacheState,
+    hash: String,
+    size: u32,
+) -> Result<String, String> {
+    if !is_valid_hash(&hash) {
+        return Err("Invalid artwork hash".to_string());
+    }
+    let cache_dir = get_artwork_cache_dir(&app)?;
+    let artwork_path = cache_dir.join(&hash);
+    let Some(thumbnail_size) = THUMBNAIL_SIZES.into_iter().find(|&s| s >= size) else {
+        read_cached_artwork(&app, &hash)?;
+        return Ok(artwork_path.display().to_string());
+    };
+    let thumbnail_path = get_thumbnail_path(&cache_dir, &hash, thumbnail_size);
+    if thumbnail_path.exists() {
+        mark_used(&artwork_path);
+    } else {
+        let data = read_cached_artwork(&app, &hash)?;
+        if create_thumbnails(&cache_dir, &hash, &data).is_err() {
+            return Ok(artwork_path.display().to_string());
+        }
+        let added_bytes = get_thumbnails_size(&cache_dir, &hash);
+        state
+            .lock()
+            .unwrap()
+            .grow(&cache_dir, added_bytes, &hash, get_cache_limit(&app));
+    }
+    Ok(thumbnail_path.display().to_string())
+}
+
+/// Removes every cached image whose hash isn't in `referenced_hashes`, along with
+/// leftovers from interrupted writes, then evicts images until the cache fits its limit.
+#[tauri::command]
+pub fn prune_artwork_cache(
+    app: AppHandle,
+    state: ArtworkCacheState,
+    referenced_hashes: Vec<String>,
+) -> Result<ArtworkPruneResult, String> {
+    let cache_dir = get_artwork_cache_dir(&app)?;
+    let referenced_hashes: HashSet<String> = referenced_hashes.into_iter().collect();
+    let mut state = state.lock().unwrap();
+    let mut removed_count = 0;
+    let mut freed_bytes = 0;
+
+    for entry in list_cache_entries(&cache_dir) {
+        if !referenced_hashes.contains(&entry.hash) && remove_cache_entry(&cache_dir, &entry.hash) {
+            removed_count += 1;
+            freed_bytes += entry.size;
+        }
+    }
+
+    let mut directories = vec![cache_dir.clone()];
+    directories.extend(
+        THUMBNAIL_SIZES
+            .iter()
+            .map(|size| cache_dir.join("thumbnails").join(size.to_string())),
+    );
+    for directory in directories {
+        let Ok(read_dir) = fs::read_dir(&directory) else {
+            continue;
+        };
+        for entry in read_dir.filter_map(|entry| entry.ok()) {
+            let name = entry.file_name().to_string_lossy().to_string();
+            // Thumbnails whose original is gone can't be reached through get_artwork_thumbnail
+            let is_orphaned_thumbnail =
+                directory != cache_dir && is_valid_hash(&name) && !cache_dir.join(&name).exists();
+            if name.ends_with(TEMP_FILE_SUFFIX) || is_orphaned_thumbnail {
+                let size = entry.metadata().map(|meta| meta.len()).unwrap_or(0);
+                if fs::remove_file(entry.path()).is_ok() {
+                    freed_bytes += size;
+                }
+            }
+        }
+    }
+
+    state.size = None;
+    state.grow(&cache_dir, 0, "", get_cache_limit(&app));
+    Ok(ArtworkPruneResult {
+        removed_count,
+        freed_bytes,
+    })
+}
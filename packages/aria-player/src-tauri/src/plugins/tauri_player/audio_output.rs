@@ -0,0 +1,554 @@
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{FromSample, SampleFormat, SizedSample, Stream, StreamConfig};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Wry};
+use tauri_plugin_store::StoreExt;
+
+/// How much audio is queued ahead of the output, in milliseconds.
+const BUFFER_LENGTH: u64 = 200;
+
+/// Where the playback engine sends audio. Outputs are created and used on the playback
+/// thread, since device streams can't be moved between threads on every platform.
+pub trait AudioOutput {
+    fn sample_rate(&self) -> u32;
+    fn channels(&self) -> usize;
+    /// Queues interleaved samples, blocking while the output's buffer is full.
+    fn write(&mut self, samples: &[f32]) -> Result<(), String>;
+    /// Discards samples that were queued but haven't been played yet.
+    fn flush(&mut self);
+    /// The number of frames that were queued but haven't been played yet.
+    fn queued_frames(&self) -> usize;
+    fn set_paused(&mut self, paused: bool);
+}
+
+#[derive(Clone, PartialEq)]
+pub enum OutputKind {
+    /// The system's default audio device.
+    Device,
+    /// Discards audio while keeping real-time pacing, for machines without a sound card.
+    Null,
+    /// Writes a WAV file in real time, to check what was played.
+    File(PathBuf),
+}
+
+impl OutputKind {
+    /// Reads the output from the `playbackoutput` and `playbackoutputfile` settings.
+    pub fn from_config(app: &AppHandle<Wry>) -> Self {
+        let Ok(store) = app.store(".app-config") else {
+            return Self::Device;
+        };
+        let output = store.get("playbackoutput");
+        match output.as_ref().and_then(|output| output.as_str()) {
+            Some("null") => Self::Null,
+            Some("file") => match store
+                .get("playbackoutputfile")
+                .and_then(|path| path.as_str().map(PathBuf::from))
+            {
+                Some(path) => Self::File(path),
+                None => Self::Null,
+            },
+            _ => Self::Device,
+        }
+    }
+}
+
+/// Opens an output, preferring the given format but using whatever the device supports.
+/// Falls back to the null output when there's no audio device.
+pub fn open_output(
+    kind: &OutputKind,
+    sample_rate: u32,
+    channels: usize,
+) -> Result<Box<dyn AudioOutput>, String> {
+    match kind {
+        OutputKind::Device => match DeviceOutput::open(sample_rate) {
+            Ok(output) => Ok(Box::new(output)),
+            Err(error) => {
+                eprintln!("Failed to open audio device, using null output: {}", error);
+                Ok(Box::new(NullOutput::new(sample_rate, channels)))
+            }
+        },
+        OutputKind::Null => Ok(Box::new(NullOutput::new(sample_rate, channels))),
+        OutputKind::File(path) => Ok(Box::new(WavFileOutput::create(
+            path,
+            sample_rate,
+            channels,
+        )?)),
+    }
+}
+
+/// Converts interleaved samples from one channel count to another. Mono is copied to
+/// every channel, downmixing to mono averages the channels, and otherwise extra channels
+/// are dropped or filled with the last one.
+pub fn convert_channels(samples: &[f32], from: usize, to: usize, converted: &mut Vec<f32>) {
+    converted.clear();
+    if from == to {
+        converted.extend_from_slice(samples);
+        return;
+    }
+    for frame in samples.chunks_exact(from) {
+        if to == 1 {
+            converted.push(frame.iter().sum::<f32>() / from as f32);
+        } else {
+            converted.extend((0..to).map(|channel| frame[channel.min(from - 1)]));
+        }
+    }
+}
+
+/// Converts between sample rates by interpolating linearly between frames, for devices
+/// that can't be opened at a track's sample rate.
+pub struct LinearResampler {
+    channels: usize,
+    /// Input frames per output frame.
+    step: f64,
+    /// Position of the next output frame, relative to `previous`.
+    position: f64,
+    previous: Vec<f32>,
+}
+
+impl LinearResampler {
+    pub fn new(from: u32, to: u32, channels: usize) -> Self {
+        Self {
+            channels,
+            step: from as f64 / to as f64,
+            position: 0.0,
+            previous: Vec::new(),
+        }
+    }
+
+    pub fn is_passthrough(&self) -> bool {
+        self.step == 1.0
+    }
+
+    pub fn process(&mut self, samples: &[f32], resampled: &mut Vec<f32>) {
+        resampled.clear();
+        if self.is_passthrough() {
+            resampled.extend_from_slice(samples);
+            return;
+        }
+        let channels = self.channels;
+        let frames = samples.len() / channels;
+        if frames == 0 {
+            return;
+        }
+        if self.previous.is_empty() {
+            self.previous = samples[..channels].to_vec();
+        }
+        // Frame 0 is the last frame of the previous call, followed by this call's frames
+        let frame = |index: usize| -> &[f32] {
+            if index == 0 {
+                &self.previous
+            } else {
+                &samples[(index - 1) * channels..index * channels]
+            }
+        };
+        while self.position < frames as f64 {
+            let index = self.position as usize;
+            let fraction = (self.position - index as f64) as f32;
+            let (current, next) = (frame(index), frame(index + 1));
+            resampled.extend(
+                current
+                    .iter()
+                    .zip(next)
+                    .map(|(current, next)| current + (next - current) * fraction),
+            );
+            self.position += self.step;
+        }
+        self.position -= frames as f64;
+        self.previous = samples[(frames - 1) * channels..].to_vec();
+    }
+}
+
+struct SharedBuffer {
+    samples: Mutex<VecDeque<f32>>,
+    space_available: Condvar,
+    paused: AtomicBool,
+}
+
+/// Plays audio on the system's default output device through cpal.
+pub struct DeviceOutput {
+    _stream: Stream,
+    buffer: Arc<SharedBuffer>,
+    sample_rate: u32,
+    channels: usize,
+    capacity: usize,
+}
+
+impl DeviceOutput {
+    pub fn open(preferred_sample_rate: u32) -> Result<Self, String> {
+        let device = cpal::default_host()
+            .default_output_device()
+            .ok_or_else(|| "No audio output device found".to_string())?;
+        let default_config = device.default_output_config().map_err(|e| e.to_string())?;
+        let sample_format = default_config.sample_format();
+        let channels = default_config.channels();
+        // Playing at the track's sample rate avoids resampling when the device allows it
+        let supports_preferred_rate = device
+            .supported_output_configs()
+            .map_err(|e| e.to_string())?
+            .any(|range| {
+                range.channels() == channels
+                    && range.sample_format() == sample_format
+                    && (range.min_sample_rate()..=range.max_sample_rate())
+                        .contains(&preferred_sample_rate)
+            });
+        let sample_rate = if supports_preferred_rate {
+            preferred_sample_rate
+        } else {
+            default_config.sample_rate()
+        };
+        let config = StreamConfig {
+            channels,
+            sample_rate,
+            buffer_size: cpal::BufferSize::Default,
+        };
+        let buffer = Arc::new(SharedBuffer {
+            samples: Mutex::new(VecDeque::new()),
+            space_available: Condvar::new(),
+            paused: AtomicBool::new(false),
+        });
+        let stream = match sample_format {
+            SampleFormat::F32 => build_stream::<f32>(&device, &config, buffer.clone()),
+            SampleFormat::I16 => build_stream::<i16>(&device, &config, buffer.clone()),
+            SampleFormat::I32 => build_stream::<i32>(&device, &config, buffer.clone()),
+            SampleFormat::U16 => build_stream::<u16>(&device, &config, buffer.clone()),
+            format => Err(format!("Unsupported sample format {}", format)),
+        }?;
+        stream.play().map_err(|e| e.to_string())?;
+        let channels = channels as usize;
+        Ok(Self {
+            _stream: stream,
+            buffer,
+            sample_rate,
+            channels,
+            capacity: (sample_rate as u64 * BUFFER_LENGTH / 1000) as usize * channels,
+        })
+    }
+}
+
+fn build_stream<T: SizedSample + FromSample<f32>>(
+    device: &cpal::Device,
+    config: &StreamConfig,
+    buffer: Arc<SharedBuffer>,
+) -> Result<Stream, String> {
+    device
+        .build_output_stream(
+            config,
+            move |data: &mut [T], _| {
+                let mut samples = buffer.samples.lock().unwrap();
+                let paused = buffer.paused.load(Ordering::Relaxed);
+                for sample in data.iter_mut() {
+                    let value = if paused {
+                        0.0
+                    } else {
+                        samples.pop_front().unwrap_or(0.0)
+                    };
+                    *sample = T::from_sample(value);
+                }
+                buffer.space_available.notify_one();
+            },
+            |error| eprintln!("Audio output error: {}", error),
+            None,
+        )
+        .map_err(|e| e.to_string())
+}
+
+impl AudioOutput for DeviceOutput {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn channels(&self) -> usize {
+        self.channels
+    }
+
+    fn write(&mut self, samples: &[f32]) -> Result<(), String> {
+        let mut queued = self.buffer.samples.lock().unwrap();
+        while queued.len() + samples.len() > self.capacity.max(samples.len()) {
+            // Time out in case the stream stops asking for audio, such as when the
+            // device is unplugged
+            let (guard, timeout) = self
+                .buffer
+                .space_available
+                .wait_timeout(queued, Duration::from_millis(BUFFER_LENGTH * 2))
+                .unwrap();
+            queued = guard;
+            if timeout.timed_out() && !self.buffer.paused.load(Ordering::Relaxed) {
+                return Err("The audio device stopped responding".to_string());
+            }
+        }
+        queued.extend(samples);
+        Ok(())
+    }
+
+    fn flush(&mut self) {
+        self.buffer.samples.lock().unwrap().clear();
+    }
+
+    fn queued_frames(&self) -> usize {
+        self.buffer.samples.lock().unwrap().len() / self.channels
+    }
+
+    fn set_paused(&mut self, paused: bool) {
+        self.buffer.paused.store(paused, Ordering::Relaxed);
+    }
+}
+
+/// Keeps outputs without a device in step with real time, so positions and events
+/// behave the same as on a sound card.
+struct Pacer {
+    sample_rate: u32,
+    started: Instant,
+    /// Frames written since `started`.
+    frames: u64,
+}
+
+impl Pacer {
+    fn new(sample_rate: u32) -> Self {
+        Self {
+            sample_rate,
+            started: Instant::now(),
+            frames: 0,
+        }
+    }
+
+    fn played_frames(&self) -> u64 {
+        let elapsed = self.started.elapsed().as_micros() as u64;
+        (elapsed * self.sample_rate as u64 / 1_000_000).min(self.frames)
+    }
+
+    fn queued_frames(&self) -> usize {
+        (self.frames - self.played_frames()) as usize
+    }
+
+    fn wait(&mut self, frames: usize) {
+        self.frames += frames as u64;
+        let buffered = self.sample_rate as u64 * BUFFER_LENGTH / 1000;
+        let ahead = self.frames.saturating_sub(buffered);
+        let target = Duration::from_micros(ahead * 1_000_000 / self.sample_rate as u64);
+        if let Some(delay) = target.checked_sub(self.started.elapsed()) {
+            thread::sleep(delay);
+        }
+    }
+
+    fn reset(&mut self) {
+        self.started = Instant::now();
+        self.frames = 0;
+    }
+}
+
+/// Discards everything written to it.
+pub struct NullOutput {
+    pacer: Pacer,
+    channels: usize,
+}
+
+impl NullOutput {
+    pub fn new(sample_rate: u32, channels: usize) -> Self {
+        Self {
+            pacer: Pacer::new(sample_rate),
+            channels,
+        }
+    }
+}
+
+impl AudioOutput for NullOutput {
+    fn sample_rate(&self) -> u32 {
+        self.pacer.sample_rate
+    }
+
+    fn channels(&self) -> usize {
+        self.channels
+    }
+
+    fn write(&mut self, samples: &[f32]) -> Result<(), String> {
+        self.pacer.wait(samples.len() / self.channels);
+        Ok(())
+    }
+
+    fn flush(&mut self) {
+        self.pacer.reset();
+    }
+
+    fn queued_frames(&self) -> usize {
+        self.pacer.queued_frames()
+    }
+
+    fn set_paused(&mut self, paused: bool) {
+        if !paused {
+            // Whatever was queued is treated as played while paused
+            self.pacer.reset();
+        }
+    }
+}
+
+/// Writes 32-bit float WAV files, with the header's sizes filled in when it's dropped.
+pub struct WavFileOutput {
+    writer: BufWriter<File>,
+    pacer: Pacer,
+    channels: usize,
+    data_size: u32,
+}
+
+impl WavFileOutput {
+    pub fn create(path: &Path, sample_rate: u32, channels: usize) -> Result<Self, String> {
+        let file = File::create(path).map_err(|e| e.to_string())?;
+        let mut writer = BufWriter::new(file);
+        let block_align = channels as u16 * 4;
+        let mut header = Vec::with_capacity(44);
+        header.extend_from_slice(b"RIFF");
+        header.extend_from_slice(&36u32.to_le_bytes());
+        header.extend_from_slice(b"WAVEfmt ");
+        header.extend_from_slice(&16u32.to_le_bytes());
+        // Format 3 is IEEE float
+        header.extend_from_slice(&3u16.to_le_bytes());
+        header.extend_from_slice(&(channels as u16).to_le_bytes());
+        header.extend_from_slice(&sample_rate.to_le_bytes());
+        header.extend_from_slice(&(sample_rate * block_align as u32).to_le_bytes());
+        header.extend_from_slice(&block_align.to_le_bytes());
+        header.extend_from_slice(&32u16.to_le_bytes());
+        header.extend_from_slice(b"data");
+        header.extend_from_slice(&0u32.to_le_bytes());
+        writer.write_all(&header).map_err(|e| e.to_string())?;
+        Ok(Self {
+            writer,
+            pacer: Pacer::new(sample_rate),
+            channels,
+            data_size: 0,
+        })
+    }
+
+    fn finish(&mut self) -> std::io::Result<()> {
+        self.writer.flush()?;
+        let file = self.writer.get_mut();
+        file.seek(SeekFrom::Start(4))?;
+        file.write_all(&(36 + self.data_size).to_le_bytes())?;
+        file.seek(SeekFrom::Start(40))?;
+        file.write_all(&self.data_size.to_le_bytes())?;
+        file.seek(SeekFrom::End(0))?;
+        Ok(())
+    }
+}
+
+impl AudioOutput for WavFileOutput {
+    fn sample_rate(&self) -> u32 {
+        self.pacer.sample_rate
+    }
+
+    fn channels(&self) -> usize {
+        self.channels
+    }
+
+    fn write(&mut self, samples: &[f32]) -> Result<(), String> {
+        for sample in samples {
+            self.writer
+                .write_all(&sample.to_le_bytes())
+                .map_err(|e| e.to_string())?;
+        }
+        self.data_size = self.data_size.saturating_add((samples.len() * 4) as u32);
+        self.pacer.wait(samples.len() / self.channels);
+        Ok(())
+    }
+
+    fn flush(&mut self) {
+        self.pacer.reset();
+    }
+
+    fn queued_frames(&self) -> usize {
+        self.pacer.queued_frames()
+    }
+
+    fn set_paused(&mut self, paused: bool) {
+        if !paused {
+            self.pacer.reset();
+        }
+    }
+}
+
+impl Drop for WavFileOutput {
+    fn drop(&mut self) {
+        if let Err(error) = self.finish() {
+            eprintln!("Failed to finish WAV file: {}", error);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resamples_to_the_output_frame_count() {
+        let mut resampler = LinearResampler::new(44100, 48000, 2);
+        let samples = vec![0.5; 44100 * 2];
+        let mut resampled = Vec::new();
+        resampler.process(&samples, &mut resampled);
+        assert!((resampled.len() / 2).abs_diff(48000) <= 1);
+        assert!(resampled.iter().all(|sample| (sample - 0.5).abs() < 1e-6));
+    }
+
+    #[test]
+    fn resampling_continues_across_calls() {
+        let ramp: Vec<f32> = (0..1000).map(|frame| frame as f32).collect();
+        let mut resampler = LinearResampler::new(48000, 44100, 1);
+        let mut chunked = Vec::new();
+        let mut resampled = Vec::new();
+        for chunk in ramp.chunks(137) {
+            resampler.process(chunk, &mut resampled);
+            chunked.extend_from_slice(&resampled);
+        }
+        assert!(chunked.len().abs_diff(919) <= 1);
+        // The first frame is repeated to start interpolating from, so output lags by a frame
+        let step = 48000.0 / 44100.0;
+        for (index, sample) in chunked.iter().enumerate() {
+            let expected = (index as f64 * step - 1.0).max(0.0) as f32;
+            assert!((sample - expected).abs() < 1e-3, "{} at {}", sample, index);
+        }
+    }
+
+    #[test]
+    fn passes_through_at_the_same_rate() {
+        let mut resampler = LinearResampler::new(44100, 44100, 2);
+        let samples = [0.1, 0.2, 0.3, 0.4];
+        let mut resampled = Vec::new();
+        resampler.process(&samples, &mut resampled);
+        assert_eq!(resampled, samples);
+    }
+
+    #[test]
+    fn converts_mono_to_stereo_and_back() {
+        let mut converted = Vec::new();
+        convert_channels(&[0.1, 0.2], 1, 2, &mut converted);
+        assert_eq!(converted, [0.1, 0.1, 0.2, 0.2]);
+
+        convert_channels(&[0.2, 0.4, -0.5, 0.5], 2, 1, &mut converted);
+        assert_eq!(converted, [0.3, 0.0]);
+    }
+
+    #[test]
+    fn fills_the_wav_header_sizes_when_dropped() {
+        let path = std::env::temp_dir().join(format!("aria-output-{}.wav", std::process::id()));
+        let mut output = WavFileOutput::create(&path, 8000, 2).unwrap();
+        output.write(&[0.25; 200]).unwrap();
+        drop(output);
+
+        let contents = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let read_u32 =
+            |offset: usize| u32::from_le_bytes(contents[offset..offset + 4].try_into().unwrap());
+        assert_eq!(contents.len(), 44 + 800);
+        assert_eq!(read_u32(4), 36 + 800);
+        assert_eq!(read_u32(40), 800);
+        assert_eq!(read_u32(24), 8000);
+        assert_eq!(
+            f32::from_le_bytes(contents[44..48].try_into().unwrap()),
+            0.25
+        );
+    }
+}
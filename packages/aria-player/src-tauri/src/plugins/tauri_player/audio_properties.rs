@@ -0,0 +1,347 @@
+use super::track_metadata::TrackMetadata;
+use lofty::config::ParseOptions;
+use lofty::file::{AudioFile, FileType, TaggedFile, TaggedFileExt};
+use lofty::iff::aiff::{AiffCompressionType, AiffFile};
+use lofty::iff::wav::{WavFile, WavFormat};
+use lofty::mp4::{AtomData, AtomIdent, Mp4Codec, Mp4File};
+use lofty::prelude::ItemKey;
+use lofty::wavpack::WavPackFile;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// How far past the ID3v2 tag to look for the first MPEG frame.
+const MPEG_SEARCH_LENGTH: usize = 16 * 1024;
+
+struct AudioFormat {
+    codec: Option<&'static str>,
+    container: Option<&'static str>,
+    lossless: Option<bool>,
+}
+
+impl AudioFormat {
+    fn new(codec: Option<&'static str>, container: &'static str, lossless: Option<bool>) -> Self {
+        Self {
+            codec,
+            container: Some(container),
+            lossless,
+        }
+    }
+}
+
+/// Details from the header of the first MPEG frame, and the Xing, Info or VBRI header inside it.
+struct MpegHeader {
+    layer: u8,
+    bitrate_mode: Option<&'static str>,
+    encoder: Option<String>,
+}
+
+fn read_properties<F: AudioFile>(path: &Path) -> Option<F> {
+    let mut file = File::open(path).ok()?;
+    let options = ParseOptions::new().read_tags(false).read_cover_art(false);
+    F::read_from(&mut file, options).ok()
+}
+
+fn get_mp4_format(path: &Path) -> AudioFormat {
+    let codec = read_properties::<Mp4File>(path).map(|file| *file.properties().codec());
+    match codec {
+        Some(Mp4Codec::AAC) => AudioFormat::new(Some("AAC"), "MP4", Some(false)),
+        Some(Mp4Codec::ALAC) => AudioFormat::new(Some("ALAC"), "MP4", Some(true)),
+        Some(Mp4Codec::MP3) => AudioFormat::new(Some("MP3"), "MP4", Some(false)),
+        Some(Mp4Codec::FLAC) => AudioFormat::new(Some("FLAC"), "MP4", Some(true)),
+        _ => AudioFormat::new(None, "MP4", None),
+    }
+}
+
+fn get_wav_format(path: &Path) -> AudioFormat {
+    let format = read_properties::<WavFile>(path).map(|file| *file.properties().format());
+    match format {
+        Some(WavFormat::PCM) | Some(WavFormat::IEEE_FLOAT) => {
+            AudioFormat::new(Some("PCM"), "WAV", Some(true))
+        }
+        // Compressed formats such as ADPCM or MP3 use other format tags
+        Some(WavFormat::Other(_)) => AudioFormat::new(None, "WAV", Some(false)),
+        None => AudioFormat::new(None, "WAV", None),
+    }
+}
+
+fn get_aiff_format(path: &Path) -> AudioFormat {
+    let Some(file) = read_properties::<AiffFile>(path) else {
+        return AudioFormat::new(None, "AIFF", None);
+    };
+    match file.properties().compression_type() {
+        None
+        | Some(AiffCompressionType::None)
+        | Some(AiffCompressionType::sowt)
+        | Some(AiffCompressionType::fl32)
+        | Some(AiffCompressionType::FL32)
+        | Some(AiffCompressionType::fl64) => AudioFormat::new(Some("PCM"), "AIFF", Some(true)),
+        Some(AiffCompressionType::alaw) | Some(AiffCompressionType::ALAW) => {
+            AudioFormat::new(Some("A-law"), "AIFF", Some(false))
+        }
+        Some(AiffCompressionType::ulaw) | Some(AiffCompressionType::ULAW) => {
+            AudioFormat::new(Some("μ-law"), "AIFF", Some(false))
+        }
+        Some(_) => AudioFormat::new(None, "AIFF", Some(false)),
+    }
+}
+
+fn get_audio_format(
+    path: &Path,
+    file_type: FileType,
+    mpeg_header: Option<&MpegHeader>,
+) -> AudioFormat {
+    match file_type {
+        FileType::Aac => AudioFormat::new(Some("AAC"), "ADTS", Some(false)),
+        FileType::Aiff => get_aiff_format(path),
+        FileType::Ape => AudioFormat::new(Some("Monkey's Audio"), "APE", Some(true)),
+        FileType::Flac => AudioFormat::new(Some("FLAC"), "FLAC", Some(true)),
+        FileType::Mpeg => {
+            let codec = match mpeg_header.map(|header| header.layer) {
+                Some(1) => "MP1",
+                Some(2) => "MP2",
+                _ => "MP3",
+            };
+            AudioFormat::new(Some(codec), "MPEG", Some(false))
+        }
+        FileType::Mp4 => get_mp4_format(path),
+        FileType::Mpc => AudioFormat::new(Some("Musepack"), "MPC", Some(false)),
+        FileType::Opus => AudioFormat::new(Some("Opus"), "Ogg", Some(false)),
+        FileType::Vorbis => AudioFormat::new(Some("Vorbis"), "Ogg", Some(false)),
+        FileType::Speex => AudioFormat::new(Some("Speex"), "Ogg", Some(false)),
+        FileType::Wav => get_wav_format(path),
+        // Hybrid WavPack files store a lossy stream, with the correction data in a separate file
+        FileType::WavPack => AudioFormat::new(
+            Some("WavPack"),
+            "WavPack",
+            read_properties::<WavPackFile>(path).map(|file| file.properties().is_lossless()),
+        ),
+        _ => AudioFormat {
+            codec: None,
+            container: None,
+            lossless: None,
+        },
+    }
+}
+
+/// The size of an ID3v2 tag, including its header and footer, from its 10 byte header.
+/// Returns 0 when there's no tag.
+fn get_id3v2_tag_size(header: &[u8]) -> u64 {
+    if header.len() < 10 || &header[..3] != b"ID3" {
+        return 0;
+    }
+    // The tag size is a syncsafe integer, with 7 bits used per byte
+    let size = header[6..10]
+        .iter()
+        .fold(0u64, |size, byte| (size << 7) | (*byte as u64 & 0x7f));
+    let footer_size = if header[5] & 0x10 != 0 { 10 } else { 0 };
+    10 + size + footer_size
+}
+
+/// Reads the first MPEG frame of a file. lofty doesn't expose whether the stream is VBR,
+/// so this looks for the header encoders write into the first frame: Xing for VBR, Info
+/// for CBR, or Fraunhofer's VBRI. LAME based encoders also add their version after it.
+fn read_mpeg_header(path: &Path) -> Option<MpegHeader> {
+    let mut file = File::open(path).ok()?;
+    let mut id3v2_header = [0u8; 10];
+    file.read_exact(&mut id3v2_header).ok()?;
+    file.seek(SeekFrom::Start(get_id3v2_tag_size(&id3v2_header)))
+        .ok()?;
+    let mut buffer = Vec::with_capacity(MPEG_SEARCH_LENGTH);
+    file.take(MPEG_SEARCH_LENGTH as u64)
+        .read_to_end(&mut buffer)
+        .ok()?;
+    parse_mpeg_header(&buffer)
+}
+
+/// Finds the first MPEG frame in `buffer` and reads its header.
+fn parse_mpeg_header(buffer: &[u8]) -> Option<MpegHeader> {
+    // Frame sync is 11 set bits, followed by a version and layer that aren't reserved
+    let start = buffer.windows(4).position(|bytes| {
+        bytes[0] == 0xff
+            && bytes[1] & 0xe0 == 0xe0
+            && bytes[1] & 0x18 != 0x08
+            && bytes[1] & 0x06 != 0
+            && bytes[2] & 0xf0 != 0xf0
+    })?;
+    let frame = &buffer[start..];
+    let layer = 4 - ((frame[1] >> 1) & 0x03);
+    let is_mpeg1 = frame[1] & 0x18 == 0x18;
+    let is_mono = frame[3] >> 6 == 0x03;
+    // The Xing header comes after the side information, which depends on the version and channels
+    let side_info_size = match (is_mpeg1, is_mono) {
+        (true, false) => 32,
+        (true, true) | (false, false) => 17,
+        (false, true) => 9,
+    };
+    let xing = frame.get(4 + side_info_size..).unwrap_or_default();
+    let has_xing = matches!(xing.get(..4), Some(b"Xing" | b"Info"));
+    let bitrate_mode = match xing.get(..4) {
+        Some(b"Xing") => Some("vbr"),
+        Some(b"Info") => Some("cbr"),
+        _ if frame.get(36..40) == Some(&b"VBRI"[..]) => Some("vbr"),
+        // Layer III VBR encoders always write a header, so a stream without one is CBR
+        _ if layer == 3 => Some("cbr"),
+        _ => None,
+    };
+    let encoder = xing
+        .get(120..129)
+        .filter(|_| has_xing)
+        .map(|bytes| {
+            String::from_utf8_lossy(bytes)
+                .trim_end_matches(['\0', ' '])
+                .to_string()
+        })
+        .filter(|encoder| {
+            !encoder.is_empty() && encoder.chars().all(|c| c.is_ascii_graphic() || c == ' ')
+        });
+    Some(MpegHeader {
+        layer,
+        bitrate_mode,
+        encoder,
+    })
+}
+
+/// Reads the encoder delay and the length of the audio in frames from the `iTunSMPB` tag,
+/// which iTunes and most other AAC encoders write to MP4 files. It's a list of hex
+/// numbers, where the second is the delay, the third is the padding and the fourth is
+/// the length without either.
+pub fn read_itunes_gapless_info(path: &Path) -> Option<(u64, u64)> {
+    let mut file = File::open(path).ok()?;
+    let options = ParseOptions::new()
+        .read_properties(false)
+        .read_cover_art(false);
+    let mp4_file = Mp4File::read_from(&mut file, options).ok()?;
+    let ident = AtomIdent::Freeform {
+        mean: "com.apple.iTunes".into(),
+        name: "iTunSMPB".into(),
+    };
+    let value = mp4_file
+        .ilst()?
+        .get(&ident)?
+        .data()
+        .find_map(|data| match data {
+            AtomData::UTF8(value) => Some(value.clone()),
+            _ => None,
+        })?;
+    let fields = value
+        .split_whitespace()
+        .map(|field| u64::from_str_radix(field, 16))
+        .collect::<Result<Vec<u64>, _>>()
+        .ok()?;
+    let delay = *fields.get(1)?;
+    let length = *fields.get(3)?;
+    (length > 0).then_some((delay, length))
+}
+
+/// Sets the technical properties of the audio stream, such as the codec and container,
+/// channel count, bit depth and whether the audio is lossless.
+pub fn set_audio_properties(metadata: &mut TrackMetadata, path: &Path, tagged_file: &TaggedFile) {
+    let properties = tagged_file.properties();
+    metadata.channels = properties.channels();
+    metadata.bit_depth = properties.bit_depth();
+    metadata.overall_bit_rate = properties.overall_bitrate().map(|bit_rate| bit_rate * 1000);
+
+    let file_type = tagged_file.file_type();
+    let mpeg_header = (file_type == FileType::Mpeg)
+        .then(|| read_mpeg_header(path))
+        .flatten();
+    let format = get_audio_format(path, file_type, mpeg_header.as_ref());
+    metadata.codec = format.codec.map(str::to_string);
+    metadata.container = format.container.map(str::to_string);
+    metadata.lossless = format.lossless;
+    metadata.bitrate_mode = mpeg_header
+        .as_ref()
+        .and_then(|header| header.bitrate_mode)
+        .map(str::to_string);
+
+    // Vorbis comment vendor strings are mapped to the encoder by lofty
+    let tag_encoder = tagged_file
+        .primary_tag()
+        .and_then(|tag| tag.get_string(ItemKey::EncoderSoftware))
+        .map(str::trim)
+        .filter(|encoder| !encoder.is_empty());
+    metadata.encoder = tag_encoder
+        .map(str::to_string)
+        .or_else(|| mpeg_header.and_then(|header| header.encoder));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An MPEG frame with `tag` written where a Xing header would start, `offset` bytes in.
+    fn frame(header: [u8; 4], offset: usize, tag: &[u8], length: usize) -> Vec<u8> {
+        let mut frame = vec![0u8; length.max(offset + tag.len())];
+        frame[..4].copy_from_slice(&header);
+        frame[offset..offset + tag.len()].copy_from_slice(tag);
+        frame
+    }
+
+    /// MPEG-1 Layer III, 128 kbps, 44.1 kHz, stereo.
+    const MPEG1_STEREO: [u8; 4] = [0xff, 0xfb, 0x90, 0x00];
+    /// The same, but mono.
+    const MPEG1_MONO: [u8; 4] = [0xff, 0xfb, 0x90, 0xc0];
+    /// MPEG-2 Layer III, stereo.
+    const MPEG2_STEREO: [u8; 4] = [0xff, 0xf3, 0x90, 0x00];
+
+    #[test]
+    fn reads_id3v2_tag_size() {
+        assert_eq!(
+            get_id3v2_tag_size(b"ID3\x04\x00\x00\x00\x00\x02\x01"),
+            10 + 257
+        );
+        // The footer flag adds another 10 bytes
+        assert_eq!(
+            get_id3v2_tag_size(b"ID3\x04\x00\x10\x00\x00\x02\x01"),
+            20 + 257
+        );
+        assert_eq!(get_id3v2_tag_size(&MPEG1_STEREO), 0);
+        assert_eq!(get_id3v2_tag_size(b"ID3"), 0);
+    }
+
+    #[test]
+    fn finds_xing_header_after_side_information() {
+        let mut buffer = vec![0u8; 3];
+        let mut xing = frame(MPEG1_STEREO, 36, b"Xing", 200);
+        xing[36 + 120..36 + 129].copy_from_slice(b"LAME3.100");
+        buffer.extend(xing);
+        let header = parse_mpeg_header(&buffer).unwrap();
+        assert_eq!(header.layer, 3);
+        assert_eq!(header.bitrate_mode, Some("vbr"));
+        assert_eq!(header.encoder.as_deref(), Some("LAME3.100"));
+
+        let header = parse_mpeg_header(&frame(MPEG1_MONO, 21, b"Info", 200)).unwrap();
+        assert_eq!(header.bitrate_mode, Some("cbr"));
+        let header = parse_mpeg_header(&frame(MPEG2_STEREO, 21, b"Xing", 200)).unwrap();
+        assert_eq!(header.bitrate_mode, Some("vbr"));
+    }
+
+    #[test]
+    fn reads_vbri_header() {
+        let header = parse_mpeg_header(&frame(MPEG1_STEREO, 36, b"VBRI", 200)).unwrap();
+        assert_eq!(header.bitrate_mode, Some("vbr"));
+        assert_eq!(header.encoder, None);
+    }
+
+    #[test]
+    fn handles_truncated_xing_header() {
+        // The buffer ends before the encoder version
+        let header = parse_mpeg_header(&frame(MPEG1_STEREO, 36, b"Xing", 60)).unwrap();
+        assert_eq!(header.bitrate_mode, Some("vbr"));
+        assert_eq!(header.encoder, None);
+        // Or before the side information is over
+        let header = parse_mpeg_header(&MPEG1_STEREO).unwrap();
+        assert_eq!(header.bitrate_mode, Some("cbr"));
+    }
+
+    #[test]
+    fn treats_layer_iii_without_header_as_cbr() {
+        let header = parse_mpeg_header(&frame(MPEG1_STEREO, 36, b"", 200)).unwrap();
+        assert_eq!(header.bitrate_mode, Some("cbr"));
+        let header = parse_mpeg_header(&frame([0xff, 0xfd, 0x90, 0x00], 36, b"", 200)).unwrap();
+        assert_eq!(header.layer, 2);
+        assert_eq!(header.bitrate_mode, None);
+        assert!(parse_mpeg_header(&[0u8; 64]).is_none());
+    }
+}
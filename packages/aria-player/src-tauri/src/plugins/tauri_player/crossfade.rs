@@ -0,0 +1,334 @@
+use super::track_metadata::TrackMetadata;
+use std::f32::consts::FRAC_PI_2;
+use tauri::{AppHandle, Wry};
+use tauri_plugin_store::StoreExt;
+
+/// Settings for fading between tracks and around pauses and seeks.
+pub struct FadeSettings {
+    /// How long tracks overlap when crossfading, in milliseconds. 0 turns crossfading off.
+    pub crossfade_duration: u64,
+    /// Whether to crossfade between tracks from the same album, which are usually meant
+    /// to play without a gap.
+    pub crossfade_same_album: bool,
+    /// How long the fades are when pausing, resuming and seeking, in milliseconds.
+    pub fade_duration: u64,
+}
+
+impl FadeSettings {
+    /// Reads the `crossfadeduration`, `crossfadesamealbum` and `fadeduration` settings.
+    pub fn from_config(app: &AppHandle<Wry>) -> Self {
+        let mut settings = Self {
+            crossfade_duration: 0,
+            crossfade_same_album: false,
+            fade_duration: 0,
+        };
+        let Ok(store) = app.store(".app-config") else {
+            return settings;
+        };
+        if let Some(duration) = store.get("crossfadeduration").and_then(|v| v.as_u64()) {
+            settings.crossfade_duration = duration;
+        }
+        if let Some(same_album) = store.get("crossfadesamealbum").and_then(|v| v.as_bool()) {
+            settings.crossfade_same_album = same_album;
+        }
+        if let Some(duration) = store.get("fadeduration").and_then(|v| v.as_u64()) {
+            settings.fade_duration = duration;
+        }
+        settings
+    }
+
+    /// Whether to crossfade from one track into the next.
+    pub fn should_crossfade(&self, from: &TrackMetadata, to: &TrackMetadata) -> bool {
+        self.crossfade_duration > 0 && (self.crossfade_same_album || !is_same_album(from, to))
+    }
+}
+
+/// Whether two tracks are from the same album, going by their album and album artist,
+/// or by the artist when there's no album artist. Tracks split from the same file by a
+/// cue sheet are always from the same album.
+pub fn is_same_album(a: &TrackMetadata, b: &TrackMetadata) -> bool {
+    if a.parent_file_path.is_some() && a.parent_file_path == b.parent_file_path {
+        return true;
+    }
+    let album_artist = |metadata: &TrackMetadata| {
+        metadata
+            .album_artist
+            .clone()
+            .or_else(|| metadata.artist.first().cloned())
+    };
+    a.album.is_some() && a.album == b.album && album_artist(a) == album_artist(b)
+}
+
+/// A gain that moves linearly towards a target, to fade audio in or out without clicks.
+pub struct GainRamp {
+    gain: f32,
+    target: f32,
+    step: f32,
+}
+
+impl GainRamp {
+    pub fn new() -> Self {
+        Self {
+            gain: 1.0,
+            target: 1.0,
+            step: 0.0,
+        }
+    }
+
+    /// Jumps straight to `gain`.
+    pub fn set(&mut self, gain: f32) {
+        self.gain = gain;
+        self.target = gain;
+        self.step = 0.0;
+    }
+
+    /// Moves from the current gain to `target` over `frames`.
+    pub fn start(&mut self, target: f32, frames: u64) {
+        if frames == 0 {
+            self.set(target);
+            return;
+        }
+        self.target = target;
+        self.step = (target - self.gain) / frames as f32;
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.gain == self.target
+    }
+
+    /// The number of frames until the gain reaches its target.
+    pub fn remaining_frames(&self) -> u64 {
+        if self.is_done() || self.step == 0.0 {
+            return 0;
+        }
+        ((self.target - self.gain) / self.step).ceil() as u64
+    }
+
+    pub fn apply(&mut self, samples: &mut [f32], channels: usize) {
+        if self.is_done() {
+            if self.gain != 1.0 {
+                for sample in samples.iter_mut() {
+                    *sample *= self.gain;
+                }
+            }
+            return;
+        }
+        for frame in samples.chunks_exact_mut(channels) {
+            self.gain += self.step;
+            if (self.step > 0.0 && self.gain >= self.target)
+                || (self.step < 0.0 && self.gain <= self.target)
+            {
+                self.gain = self.target;
+            }
+            for sample in frame.iter_mut() {
+                *sample *= self.gain;
+            }
+        }
+    }
+}
+
+/// Mixes the end of one track into the start of the next, with equal power curves so
+/// the volume doesn't dip in the middle.
+pub struct Crossfade {
+    /// Frames the tracks overlap for.
+    length: u64,
+    /// Frames mixed so far.
+    position: u64,
+    /// Samples from the outgoing track that haven't been mixed yet.
+    pending: Vec<f32>,
+}
+
+impl Crossfade {
+    pub fn new(length: u64) -> Self {
+        Self {
+            length,
+            position: 0,
+            pending: Vec::new(),
+        }
+    }
+
+    /// The number of samples from the outgoing track needed to mix `samples` more.
+    pub fn needed_samples(&self, samples: usize) -> usize {
+        samples.saturating_sub(self.pending.len())
+    }
+
+    /// Queues samples from the outgoing track to be mixed.
+    pub fn push(&mut self, samples: &[f32]) {
+        self.pending.extend_from_slice(samples);
+    }
+
+    /// Mixes the outgoing track into `samples` from the incoming track. Once the
+    /// outgoing track runs out, the incoming track carries on fading in by itself.
+    pub fn mix(&mut self, samples: &mut [f32], channels: usize) {
+        let mut mixed = 0;
+        for frame in samples.chunks_exact_mut(channels) {
+            if self.is_done() {
+                break;
+            }
+            let progress = (self.position as f32 / self.length as f32).min(1.0) * FRAC_PI_2;
+            let (fade_out, fade_in) = (progress.cos(), progress.sin());
+            for sample in frame.iter_mut() {
+                let outgoing = self.pending.get(mixed).copied().unwrap_or(0.0);
+                *sample = *sample * fade_in + outgoing * fade_out;
+                mixed += 1;
+            }
+            self.position += 1;
+        }
+        self.pending.drain(..mixed.min(self.pending.len()));
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.position >= self.length
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(album: Option<&str>, album_artist: Option<&str>, artist: &[&str]) -> TrackMetadata {
+        TrackMetadata {
+            album: album.map(str::to_string),
+            album_artist: album_artist.map(str::to_string),
+            artist: artist.iter().map(|artist| artist.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    fn settings(crossfade_same_album: bool) -> FadeSettings {
+        FadeSettings {
+            crossfade_duration: 5000,
+            crossfade_same_album,
+            fade_duration: 0,
+        }
+    }
+
+    #[test]
+    fn tracks_from_the_same_cue_sheet_are_the_same_album() {
+        let mut a = track(None, None, &[]);
+        let mut b = track(Some("Other"), None, &[]);
+        a.parent_file_path = Some("album.flac".to_string());
+        b.parent_file_path = Some("album.flac".to_string());
+        assert!(is_same_album(&a, &b));
+
+        b.parent_file_path = Some("other.flac".to_string());
+        assert!(!is_same_album(&a, &b));
+    }
+
+    #[test]
+    fn compares_album_and_album_artist() {
+        let a = track(Some("Album"), Some("Band"), &["Singer"]);
+        assert!(is_same_album(
+            &a,
+            &track(Some("Album"), Some("Band"), &["Guest"])
+        ));
+        assert!(!is_same_album(
+            &a,
+            &track(Some("Album"), Some("Other Band"), &["Singer"])
+        ));
+        assert!(!is_same_album(
+            &a,
+            &track(Some("Other Album"), Some("Band"), &["Singer"])
+        ));
+    }
+
+    #[test]
+    fn falls_back_to_the_artist_without_an_album_artist() {
+        let a = track(Some("Album"), None, &["Band", "Guest"]);
+        assert!(is_same_album(&a, &track(Some("Album"), None, &["Band"])));
+        assert!(is_same_album(
+            &a,
+            &track(Some("Album"), Some("Band"), &["Singer"])
+        ));
+        assert!(!is_same_album(
+            &a,
+            &track(Some("Album"), None, &["Other Band"])
+        ));
+    }
+
+    #[test]
+    fn tracks_without_an_album_are_never_the_same_album() {
+        let a = track(None, Some("Band"), &["Band"]);
+        assert!(!is_same_album(&a, &a));
+    }
+
+    #[test]
+    fn crossfades_only_between_albums_unless_enabled() {
+        let a = track(Some("Album"), Some("Band"), &[]);
+        let b = track(Some("Album"), Some("Band"), &[]);
+        let c = track(Some("Other Album"), Some("Band"), &[]);
+        assert!(!settings(false).should_crossfade(&a, &b));
+        assert!(settings(false).should_crossfade(&a, &c));
+        assert!(settings(true).should_crossfade(&a, &b));
+
+        let disabled = FadeSettings {
+            crossfade_duration: 0,
+            ..settings(true)
+        };
+        assert!(!disabled.should_crossfade(&a, &c));
+    }
+
+    #[test]
+    fn gain_ramp_reaches_its_target() {
+        let mut ramp = GainRamp::new();
+        ramp.start(0.0, 4);
+        assert_eq!(ramp.remaining_frames(), 4);
+
+        let mut samples = [1.0; 2];
+        ramp.apply(&mut samples, 2);
+        assert_eq!(samples, [0.75, 0.75]);
+        assert_eq!(ramp.remaining_frames(), 3);
+
+        // The ramp stops at its target partway through the samples
+        let mut samples = [1.0; 5];
+        ramp.apply(&mut samples, 1);
+        assert_eq!(samples, [0.5, 0.25, 0.0, 0.0, 0.0]);
+        assert!(ramp.is_done());
+        assert_eq!(ramp.remaining_frames(), 0);
+
+        ramp.start(1.0, 2);
+        let mut samples = [1.0; 3];
+        ramp.apply(&mut samples, 1);
+        assert_eq!(samples, [0.5, 1.0, 1.0]);
+        assert!(ramp.is_done());
+    }
+
+    #[test]
+    fn crossfade_keeps_equal_power() {
+        let mut crossfade = Crossfade::new(4);
+        crossfade.push(&[1.0; 4]);
+        let mut samples = [0.0; 4];
+        crossfade.mix(&mut samples, 1);
+        // The outgoing track starts at full volume, and the two gains always add up to
+        // the same power
+        assert_eq!(samples[0], 1.0);
+        for (position, fade_out) in samples.iter().enumerate() {
+            let fade_in = (position as f32 / 4.0 * FRAC_PI_2).sin();
+            assert!((fade_out.powi(2) + fade_in.powi(2) - 1.0).abs() < 1e-6);
+        }
+        assert!(crossfade.is_done());
+
+        // The incoming track is at full volume once the crossfade is done
+        let mut samples = [0.5; 2];
+        crossfade.mix(&mut samples, 1);
+        assert_eq!(samples, [0.5; 2]);
+    }
+
+    #[test]
+    fn incoming_track_fades_in_alone_once_the_outgoing_track_ends() {
+        let mut crossfade = Crossfade::new(4);
+        crossfade.push(&[1.0; 2]);
+        assert_eq!(crossfade.needed_samples(8), 6);
+
+        let mut samples = [1.0; 8];
+        crossfade.mix(&mut samples, 2);
+        assert_eq!(samples[..2], [1.0, 1.0]);
+        for frame in 1..4 {
+            let fade_in = (frame as f32 / 4.0 * FRAC_PI_2).sin();
+            assert!((samples[frame * 2] - fade_in).abs() < 1e-6);
+            assert_eq!(samples[frame * 2], samples[frame * 2 + 1]);
+        }
+        assert!(crossfade.is_done());
+        assert_eq!(crossfade.needed_samples(8), 8);
+    }
+}
@@ -0,0 +1,520 @@
+use super::track_metadata::{parse_year, TrackMetadata};
+use super::{decode_text, get_metadata, get_playback_metadata};
+use lofty::ape::ApeFile;
+use lofty::config::ParseOptions;
+use lofty::file::{AudioFile, FileType};
+use lofty::flac::FlacFile;
+use lofty::tag::ItemValue;
+use lofty::wavpack::WavPackFile;
+use serde::Serialize;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use tauri::AppHandle;
+
+/// Separates the parent file's path from the track number in a virtual track URI,
+/// such as `/music/album.flac#track=3`.
+const VIRTUAL_TRACK_SEPARATOR: &str = "#track=";
+
+/// CD frames per second, the unit of the last part of a cue sheet timestamp.
+const FRAMES_PER_SECOND: u64 = 75;
+
+#[derive(Default)]
+pub struct CueSheet {
+    pub title: Option<String>,
+    pub performer: Option<String>,
+    pub genre: Option<String>,
+    pub date: Option<String>,
+    pub tracks: Vec<CueTrack>,
+}
+
+#[derive(Default)]
+pub struct CueTrack {
+    pub number: u32,
+    /// The `FILE` the track is in, as written in the sheet.
+    pub file: Option<String>,
+    pub title: Option<String>,
+    pub performer: Option<String>,
+    pub songwriter: Option<String>,
+    pub isrc: Option<String>,
+    /// Start of `INDEX 01` in milliseconds, so any pregap belongs to the previous track.
+    pub start: u64,
+}
+
+/// The part of a file a virtual track plays, in milliseconds.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VirtualTrack {
+    pub file_path: String,
+    pub number: u32,
+    pub start: u64,
+    /// `None` for the last track, which plays until the end of the file.
+    pub end: Option<u64>,
+}
+
+pub fn get_virtual_track_uri(path: &Path, number: u32) -> String {
+    format!("{}{}{}", path.display(), VIRTUAL_TRACK_SEPARATOR, number)
+}
+
+/// Splits a virtual track URI into the parent file's path and the track number.
+pub fn parse_virtual_track_uri(uri: &str) -> Option<(&str, u32)> {
+    let (file_path, number) = uri.rsplit_once(VIRTUAL_TRACK_SEPARATOR)?;
+    if number.is_empty() || !number.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    Some((file_path, number.parse().ok()?))
+}
+
+/// The file behind a track URI, which is the URI itself unless it's a virtual track.
+pub fn get_file_path(uri: &str) -> &Path {
+    Path::new(parse_virtual_track_uri(uri).map_or(uri, |(file_path, _)| file_path))
+}
+
+/// Parses a `mm:ss:ff` timestamp into milliseconds.
+fn parse_timestamp(value: &str) -> Option<u64> {
+    let mut parts = value.trim().splitn(3, ':');
+    let minutes: u64 = parts.next()?.parse().ok()?;
+    let seconds: u64 = parts.next()?.parse().ok()?;
+    let frames: u64 = parts.next()?.parse().ok()?;
+    if seconds >= 60 || frames >= FRAMES_PER_SECOND {
+        return None;
+    }
+    Some((minutes * 60 + seconds) * 1000 + frames * 1000 / FRAMES_PER_SECOND)
+}
+
+/// Reads a quoted or unquoted argument, returning it and the rest of the line.
+fn split_argument(text: &str) -> (String, &str) {
+    let text = text.trim_start();
+    if let Some(quoted) = text.strip_prefix('"') {
+        return match quoted.find('"') {
+            Some(end) => (quoted[..end].to_string(), &quoted[end + 1..]),
+            None => (quoted.to_string(), ""),
+        };
+    }
+    match text.split_once(char::is_whitespace) {
+        Some((argument, rest)) => (argument.to_string(), rest),
+        None => (text.to_string(), ""),
+    }
+}
+
+/// Reads the rest of a line as a single value, such as a title.
+fn parse_value(text: &str) -> Option<String> {
+    let text = text.trim();
+    let value = match text.strip_prefix('"') {
+        Some(quoted) => quoted.strip_suffix('"').unwrap_or(quoted),
+        None => text,
+    };
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+/// Adds a track once all of its commands have been read. Tracks without an index can't
+/// be played, so they're left out.
+fn finish_track(
+    sheet: &mut CueSheet,
+    track: Option<CueTrack>,
+    start: Option<u64>,
+    pregap_start: Option<u64>,
+) {
+    if let (Some(mut track), Some(start)) = (track, start.or(pregap_start)) {
+        track.start = start;
+        sheet.tracks.push(track);
+    }
+}
+
+pub fn parse_cue_sheet(text: &str) -> CueSheet {
+    let mut sheet = CueSheet::default();
+    let mut file = None;
+    let mut track: Option<CueTrack> = None;
+    let mut track_start = None;
+    let mut pregap_start = None;
+    let mut seen_track = false;
+
+    for line in text.trim_start_matches('\u{feff}').lines() {
+        let line = line.trim();
+        let (command, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        match command.to_ascii_uppercase().as_str() {
+            "FILE" => {
+                // The last word is the file type, such as WAVE
+                let (name, _) = split_argument(rest);
+                file = Some(name);
+            }
+            "TRACK" => {
+                finish_track(&mut sheet, track.take(), track_start, pregap_start);
+                (track_start, pregap_start) = (None, None);
+                seen_track = true;
+                let (number, kind) = split_argument(rest);
+                if kind.trim().eq_ignore_ascii_case("AUDIO") {
+                    track = number.parse().ok().map(|number| CueTrack {
+                        number,
+                        file: file.clone(),
+                        ..Default::default()
+                    });
+                }
+            }
+            "INDEX" => {
+                let (index, timestamp) = split_argument(rest);
+                match index.parse::<u32>() {
+                    Ok(0) => pregap_start = parse_timestamp(timestamp),
+                    Ok(1) => track_start = parse_timestamp(timestamp),
+                    _ => {}
+                }
+            }
+            "TITLE" | "PERFORMER" | "SONGWRITER" | "ISRC" => {
+                let value = parse_value(rest);
+                match (&mut track, command.to_ascii_uppercase().as_str()) {
+                    (Some(track), "TITLE") => track.title = value,
+                    (Some(track), "PERFORMER") => track.performer = value,
+                    (Some(track), "SONGWRITER") => track.songwriter = value,
+                    (Some(track), "ISRC") => track.isrc = value,
+                    // Commands before the first track describe the whole album
+                    (None, "TITLE") if !seen_track => sheet.title = value,
+                    (None, "PERFORMER") if !seen_track => sheet.performer = value,
+                    _ => {}
+                }
+            }
+            "REM" => {
+                let (key, value) = split_argument(rest);
+                match key.to_ascii_uppercase().as_str() {
+                    "GENRE" => sheet.genre = parse_value(value),
+                    "DATE" => sheet.date = parse_value(value),
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+    finish_track(&mut sheet, track, track_start, pregap_start);
+    sheet
+}
+
+fn read_cue_file(path: &Path) -> Option<CueSheet> {
+    let contents = fs::read(path).ok()?;
+    Some(parse_cue_sheet(&decode_text(&contents)))
+}
+
+fn get_stem(file_name: &str) -> String {
+    Path::new(file_name)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+/// Checks whether a sheet's `FILE` entry refers to `path`. Rips are often converted
+/// after the sheet was made, so `album.wav` in the sheet also matches `album.flac`.
+fn is_referenced_file(file: &str, path: &Path) -> bool {
+    let file_name = file.rsplit(['/', '\\']).next().unwrap_or(file);
+    let audio_file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    file_name.to_lowercase() == audio_file_name || get_stem(file_name) == get_stem(&audio_file_name)
+}
+
+fn read_sidecar_cue_sheet(path: &Path) -> Option<CueSheet> {
+    let directory = path.parent()?;
+    let mut cue_paths: Vec<PathBuf> = fs::read_dir(directory)
+        .ok()?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|cue_path| {
+            cue_path
+                .extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("cue"))
+        })
+        .collect();
+    // Prefer the sheet named after the audio file when several reference it
+    let audio_stem = get_stem(&path.to_string_lossy());
+    cue_paths.sort_by_key(|cue_path| get_stem(&cue_path.to_string_lossy()) != audio_stem);
+    cue_paths.into_iter().find_map(|cue_path| {
+        let mut sheet = read_cue_file(&cue_path)?;
+        sheet.tracks.retain(|track| {
+            track
+                .file
+                .as_deref()
+                .is_some_and(|file| is_referenced_file(file, path))
+        });
+        (!sheet.tracks.is_empty()).then_some(sheet)
+    })
+}
+
+/// Finds the files in a `.cue` file's folder that the sheet has tracks in.
+pub fn get_referenced_files(cue_path: &Path) -> Vec<PathBuf> {
+    let (Some(sheet), Some(directory)) = (read_cue_file(cue_path), cue_path.parent()) else {
+        return Vec::new();
+    };
+    let Ok(entries) = fs::read_dir(directory) else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path != cue_path
+                && sheet.tracks.iter().any(|track| {
+                    track
+                        .file
+                        .as_deref()
+                        .is_some_and(|file| is_referenced_file(file, path))
+                })
+        })
+        .collect()
+}
+
+/// FLAC files store an embedded sheet in their Vorbis comments, while Monkey's Audio
+/// and WavPack use their APE tag. lofty's generic tag drops the field, so it's read
+/// from the format's own tag.
+fn read_embedded_cue_sheet(path: &Path) -> Option<CueSheet> {
+    let file_type = FileType::from_path(path)?;
+    if !matches!(
+        file_type,
+        FileType::Flac | FileType::Ape | FileType::WavPack
+    ) {
+        return None;
+    }
+    let mut file = File::open(path).ok()?;
+    let options = ParseOptions::new()
+        .read_properties(false)
+        .read_cover_art(false);
+    let text = match file_type {
+        FileType::Flac => FlacFile::read_from(&mut file, options)
+            .ok()?
+            .vorbis_comments()?
+            .get("CUESHEET")
+            .map(str::to_string),
+        FileType::Ape => ApeFile::read_from(&mut file, options)
+            .ok()?
+            .ape()?
+            .get("CUESHEET")
+            .and_then(|item| match item.value() {
+                ItemValue::Text(text) => Some(text.clone()),
+                _ => None,
+            }),
+        _ => WavPackFile::read_from(&mut file, options)
+            .ok()?
+            .ape()?
+            .get("CUESHEET")
+            .and_then(|item| match item.value() {
+                ItemValue::Text(text) => Some(text.clone()),
+                _ => None,
+            }),
+    }?;
+    // An embedded sheet always describes its own file, whatever name it was made with
+    Some(parse_cue_sheet(&text))
+}
+
+/// Finds the cue sheet for an audio file, preferring a sidecar `.cue` file over an
+/// embedded one. Sheets with fewer than two tracks in the file are ignored, since there's
+/// nothing to split.
+fn find_cue_sheet(path: &Path) -> Option<CueSheet> {
+    read_sidecar_cue_sheet(path)
+        .filter(|sheet| sheet.tracks.len() > 1)
+        .or_else(|| read_embedded_cue_sheet(path).filter(|sheet| sheet.tracks.len() > 1))
+}
+
+fn get_virtual_track_uris(path: &Path, sheet: &CueSheet) -> Vec<String> {
+    sheet
+        .tracks
+        .iter()
+        .map(|track| get_virtual_track_uri(path, track.number))
+        .collect()
+}
+
+/// Returns a virtual track URI for each track in the file's sidecar cue sheet, if it has
+/// one. Scans don't look for embedded sheets, which would mean opening every file, so
+/// those are found by `get_embedded_cue_track_uris` once the file's metadata is read.
+/// `check_sidecar` can be turned off when the folder has no `.cue` files.
+pub fn get_cue_track_uris(path: &Path, check_sidecar: bool) -> Option<Vec<String>> {
+    if !check_sidecar {
+        return None;
+    }
+    let sheet = read_sidecar_cue_sheet(path).filter(|sheet| sheet.tracks.len() > 1)?;
+    Some(get_virtual_track_uris(path, &sheet))
+}
+
+/// Returns a virtual track URI for each track in the file's embedded cue sheet, or
+/// nothing if it doesn't have one.
+pub fn get_embedded_cue_track_uris(path: &Path) -> Vec<String> {
+    read_embedded_cue_sheet(path)
+        .filter(|sheet| sheet.tracks.len() > 1)
+        .map(|sheet| get_virtual_track_uris(path, &sheet))
+        .unwrap_or_default()
+}
+
+fn find_cue_track(file_path: &str, number: u32) -> Result<(CueSheet, usize), String> {
+    let sheet = find_cue_sheet(Path::new(file_path))
+        .ok_or_else(|| "The file no longer has a cue sheet".to_string())?;
+    let index = sheet
+        .tracks
+        .iter()
+        .position(|track| track.number == number)
+        .ok_or_else(|| format!("Track {} isn't in the cue sheet", number))?;
+    Ok((sheet, index))
+}
+
+/// Reads the metadata of a virtual track, using the parent file's metadata for anything
+/// the cue sheet doesn't set.
+pub fn get_cue_track_metadata(
+    app: AppHandle,
+    file_path: &str,
+    number: u32,
+) -> Result<TrackMetadata, String> {
+    let (sheet, index) = find_cue_track(file_path, number)?;
+    let metadata = get_metadata(app, file_path.to_string())?;
+    Ok(set_cue_track_metadata(metadata, file_path, &sheet, index))
+}
+
+/// Reads what playback needs of a virtual track, like `get_playback_metadata` does for files.
+pub fn get_cue_track_playback_metadata(
+    file_path: &str,
+    number: u32,
+) -> Result<TrackMetadata, String> {
+    let (sheet, index) = find_cue_track(file_path, number)?;
+    let metadata = get_playback_metadata(file_path)?;
+    Ok(set_cue_track_metadata(metadata, file_path, &sheet, index))
+}
+
+/// Narrows the parent file's metadata down to one of its cue sheet's tracks.
+fn set_cue_track_metadata(
+    mut metadata: TrackMetadata,
+    file_path: &str,
+    sheet: &CueSheet,
+    index: usize,
+) -> TrackMetadata {
+    let track = &sheet.tracks[index];
+    let end = sheet
+        .tracks
+        .get(index + 1)
+        .map_or(metadata.duration, |next| next.start);
+
+    metadata.duration = end.saturating_sub(track.start);
+    metadata.start_offset = Some(track.start);
+    metadata.parent_file_path = Some(file_path.to_string());
+    metadata.track = Some(track.number);
+    metadata.track_total = Some(sheet.tracks.len() as u32);
+    metadata.title = track.title.clone().or(metadata.title);
+    if let Some(performer) = track.performer.as_ref().or(sheet.performer.as_ref()) {
+        metadata.artist = vec![performer.clone()];
+    }
+    if let Some(songwriter) = &track.songwriter {
+        metadata.composer = vec![songwriter.clone()];
+    }
+    metadata.album = sheet.title.clone().or(metadata.album);
+    metadata.album_artist = sheet.performer.clone().or(metadata.album_artist);
+    if let Some(genre) = &sheet.genre {
+        metadata.genre = vec![genre.clone()];
+    }
+    metadata.year = sheet.date.as_deref().and_then(parse_year).or(metadata.year);
+    metadata.isrc = track.isrc.clone().or(metadata.isrc);
+    // Track level ReplayGain and MusicBrainz values describe the whole file
+    metadata.replay_gain_track_gain = None;
+    metadata.replay_gain_track_peak = None;
+    metadata.r128_track_gain = None;
+    metadata.music_brainz_recording_id = None;
+    metadata.music_brainz_track_id = None;
+    metadata.cue_track_uris = Vec::new();
+    metadata
+}
+
+/// Returns the file and position a virtual track plays from.
+#[tauri::command]
+pub fn get_cue_track(uri: String) -> Result<VirtualTrack, String> {
+    let (file_path, number) =
+        parse_virtual_track_uri(&uri).ok_or_else(|| "Not a virtual track".to_string())?;
+    let (sheet, index) = find_cue_track(file_path, number)?;
+    Ok(VirtualTrack {
+        file_path: file_path.to_string(),
+        number,
+        start: sheet.tracks[index].start,
+        end: sheet.tracks.get(index + 1).map(|next| next.start),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHEET: &str = "\u{feff}REM GENRE Rock
+REM DATE 1999
+PERFORMER \"The Band\"
+TITLE \"The Album\"
+FILE \"album.wav\" WAVE
+  TRACK 01 AUDIO
+    TITLE \"First\"
+    INDEX 01 00:00:00
+  TRACK 02 AUDIO
+    TITLE \"Second\"
+    PERFORMER \"Guest\"
+    INDEX 00 03:58:50
+    INDEX 01 04:00:37
+  TRACK 03 AUDIO
+    TITLE Third
+    INDEX 00 07:30:00
+  TRACK 04 DATA
+    INDEX 01 09:00:00
+  TRACK 05 AUDIO
+    TITLE \"No index\"
+";
+
+    #[test]
+    fn parses_timestamps_in_cd_frames() {
+        assert_eq!(parse_timestamp("00:00:00"), Some(0));
+        assert_eq!(parse_timestamp("00:01:00"), Some(1_000));
+        assert_eq!(parse_timestamp("00:00:74"), Some(986));
+        assert_eq!(parse_timestamp("04:00:37"), Some(240_493));
+        assert_eq!(parse_timestamp("99:59:74"), Some(5_999_986));
+        assert_eq!(parse_timestamp("00:00:75"), None);
+        assert_eq!(parse_timestamp("00:60:00"), None);
+        assert_eq!(parse_timestamp("00:00"), None);
+    }
+
+    #[test]
+    fn parses_album_fields() {
+        let sheet = parse_cue_sheet(SHEET);
+        assert_eq!(sheet.title.as_deref(), Some("The Album"));
+        assert_eq!(sheet.performer.as_deref(), Some("The Band"));
+        assert_eq!(sheet.genre.as_deref(), Some("Rock"));
+        assert_eq!(sheet.date.as_deref(), Some("1999"));
+    }
+
+    #[test]
+    fn parses_audio_tracks_with_an_index() {
+        let sheet = parse_cue_sheet(SHEET);
+        let numbers: Vec<u32> = sheet.tracks.iter().map(|track| track.number).collect();
+        assert_eq!(numbers, vec![1, 2, 3]);
+        let second = &sheet.tracks[1];
+        assert_eq!(second.file.as_deref(), Some("album.wav"));
+        assert_eq!(second.title.as_deref(), Some("Second"));
+        assert_eq!(second.performer.as_deref(), Some("Guest"));
+        assert_eq!(sheet.tracks[2].title.as_deref(), Some("Third"));
+    }
+
+    #[test]
+    fn starts_tracks_at_index_01_and_falls_back_to_index_00() {
+        let sheet = parse_cue_sheet(SHEET);
+        assert_eq!(sheet.tracks[0].start, 0);
+        // The pregap in INDEX 00 belongs to the previous track
+        assert_eq!(sheet.tracks[1].start, 240_493);
+        assert_eq!(sheet.tracks[2].start, 450_000);
+    }
+
+    #[test]
+    fn parses_virtual_track_uris() {
+        let uri = get_virtual_track_uri(Path::new("/music/album.flac"), 3);
+        assert_eq!(uri, "/music/album.flac#track=3");
+        assert_eq!(
+            parse_virtual_track_uri(&uri),
+            Some(("/music/album.flac", 3))
+        );
+        assert_eq!(parse_virtual_track_uri("/music/a.flac#track="), None);
+        assert_eq!(parse_virtual_track_uri("/music/a.flac#track=x"), None);
+        assert_eq!(get_file_path(&uri), Path::new("/music/album.flac"));
+        assert_eq!(get_file_path("/music/b.mp3"), Path::new("/music/b.mp3"));
+    }
+
+    #[test]
+    fn matches_referenced_files_by_name_or_stem() {
+        let path = Path::new("/music/Album.flac");
+        assert!(is_referenced_file("album.flac", path));
+        assert!(is_referenced_file("C:\\rips\\album.wav", path));
+        assert!(!is_referenced_file("other.wav", path));
+    }
+}
@@ -0,0 +1,186 @@
+use std::fs::File;
+use std::path::Path;
+use symphonia::core::codecs::audio::well_known::CODEC_ID_OPUS;
+use symphonia::core::codecs::audio::{AudioDecoder as CodecDecoder, AudioDecoderOptions};
+use symphonia::core::errors::Error;
+use symphonia::core::formats::probe::Hint;
+use symphonia::core::formats::{FormatOptions, FormatReader, SeekMode, SeekTo, TrackType};
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::units::{Time, TimeBase, Timestamp};
+
+/// Returned when symphonia can't read a file's format or decode its codec, such as Opus,
+/// Monkey's Audio and WavPack, so the frontend can play the file through the webview.
+pub const UNSUPPORTED_CODEC_ERROR: &str = "unsupported_codec";
+
+fn open_error(error: Error) -> String {
+    match error {
+        Error::Unsupported(_) => UNSUPPORTED_CODEC_ERROR.to_string(),
+        error => error.to_string(),
+    }
+}
+
+/// Decodes the default audio track of a file to interleaved `f32` samples.
+pub struct AudioDecoder {
+    format: Box<dyn FormatReader>,
+    decoder: Box<dyn CodecDecoder>,
+    track_id: u32,
+    sample_rate: u32,
+    channels: usize,
+    time_base: Option<TimeBase>,
+    samples: Vec<f32>,
+    /// The frame the next decoded packet starts at, counted from the start of the stream.
+    next_frame: u64,
+    /// Decoded frames before this are dropped, since seeking lands on a packet boundary
+    /// before the requested position, and some encoders add silence at the start.
+    first_frame: u64,
+    /// Frames added by the encoder before the audio, when the decoder doesn't remove them.
+    delay: u64,
+    /// The frame after the last one to play, for streams with padding at the end.
+    end_frame: Option<u64>,
+}
+
+impl AudioDecoder {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let file = File::open(path).map_err(|e| e.to_string())?;
+        let source = MediaSourceStream::new(Box::new(file), Default::default());
+        let mut hint = Hint::new();
+        if let Some(extension) = path.extension().and_then(|ext| ext.to_str()) {
+            hint.with_extension(extension);
+        }
+        let format = symphonia::default::get_probe()
+            .probe(
+                &hint,
+                source,
+                FormatOptions::default(),
+                MetadataOptions::default(),
+            )
+            .map_err(open_error)?;
+        let track = format
+            .default_track(TrackType::Audio)
+            .ok_or_else(|| "No audio track found".to_string())?;
+        let codec_params = track
+            .codec_params
+            .as_ref()
+            .and_then(|params| params.audio())
+            .ok_or_else(|| UNSUPPORTED_CODEC_ERROR.to_string())?;
+        let sample_rate = codec_params
+            .sample_rate
+            .ok_or_else(|| "Unknown sample rate".to_string())?;
+        let channels = codec_params
+            .channels
+            .as_ref()
+            .map(|channels| channels.count())
+            .ok_or_else(|| "Unknown channel layout".to_string())?;
+        // symphonia can read Ogg Opus files, but has no decoder for Opus itself
+        if codec_params.codec == CODEC_ID_OPUS {
+            return Err(UNSUPPORTED_CODEC_ERROR.to_string());
+        }
+        let decoder = symphonia::default::get_codecs()
+            .make_audio_decoder(codec_params, &AudioDecoderOptions::default())
+            .map_err(open_error)?;
+        Ok(Self {
+            track_id: track.id,
+            time_base: track.time_base,
+            format,
+            decoder,
+            sample_rate,
+            channels,
+            samples: Vec::new(),
+            next_frame: 0,
+            first_frame: 0,
+            delay: 0,
+            end_frame: None,
+        })
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    /// Removes encoder delay and padding that the codec doesn't handle itself, such as
+    /// AAC in MP4 files. `length` is the number of frames of actual audio after the delay.
+    pub fn set_gapless_trim(&mut self, delay: u64, length: u64) {
+        self.delay = delay;
+        self.first_frame = self.first_frame.max(delay);
+        self.end_frame = Some(delay + length);
+    }
+
+    fn get_frame(&self, ts: Timestamp) -> u64 {
+        match self.time_base {
+            Some(time_base) => {
+                let time = time_base.calc_time_saturating(ts);
+                (time.as_secs_f64() * self.sample_rate as f64)
+                    .round()
+                    .max(0.0) as u64
+            }
+            None => ts.get().max(0) as u64,
+        }
+    }
+
+    /// Moves to `position` in milliseconds, so the next samples start exactly there.
+    pub fn seek(&mut self, position: u64) -> Result<(), String> {
+        let frame = position * self.sample_rate as u64 / 1000 + self.delay;
+        // Rounded up so the timestamp the demuxer calculates isn't a frame early
+        let seeked_to = self
+            .format
+            .seek(
+                SeekMode::Accurate,
+                SeekTo::Time {
+                    time: Time::from_nanos_u64(
+                        (frame as u128 * 1_000_000_000).div_ceil(self.sample_rate as u128) as u64,
+                    ),
+                    track_id: Some(self.track_id),
+                },
+            )
+            .map_err(|e| e.to_string())?;
+        self.decoder.reset();
+        self.next_frame = self.get_frame(seeked_to.actual_ts);
+        self.first_frame = self.get_frame(seeked_to.required_ts).max(self.delay);
+        Ok(())
+    }
+
+    /// Decodes the next packet, returning `None` at the end of the track.
+    /// Packets with corrupt data are skipped rather than ending playback.
+    pub fn next_samples(&mut self) -> Result<Option<&[f32]>, String> {
+        loop {
+            if self
+                .end_frame
+                .is_some_and(|end_frame| self.next_frame >= end_frame)
+            {
+                return Ok(None);
+            }
+            let Some(packet) = self.format.next_packet().map_err(|e| e.to_string())? else {
+                return Ok(None);
+            };
+            if packet.track_id != self.track_id {
+                continue;
+            }
+            match self.decoder.decode(&packet) {
+                Ok(buffer) => {
+                    buffer.copy_to_vec_interleaved(&mut self.samples);
+                    let frames = (self.samples.len() / self.channels) as u64;
+                    let packet_start = self.next_frame;
+                    self.next_frame += frames;
+                    let start = self.first_frame.saturating_sub(packet_start).min(frames);
+                    let end = self.end_frame.map_or(frames, |end_frame| {
+                        end_frame.saturating_sub(packet_start).min(frames)
+                    });
+                    if start >= end {
+                        continue;
+                    }
+                    let channels = self.channels;
+                    return Ok(Some(
+                        &self.samples[start as usize * channels..end as usize * channels],
+                    ));
+                }
+                Err(Error::DecodeError(_)) => continue,
+                Err(e) => return Err(e.to_string()),
+            }
+        }
+    }
+}
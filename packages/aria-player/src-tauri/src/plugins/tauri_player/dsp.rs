@@ -0,0 +1,670 @@
+use super::decoder::AudioDecoder;
+use super::playback::PlaybackEngine;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::f64::consts::PI;
+use std::path::Path;
+use std::sync::Mutex;
+use tauri::{AppHandle, State, Wry};
+use tauri_plugin_store::StoreExt;
+
+/// Centre frequencies of the default 10-band equalizer, in Hz.
+pub const DEFAULT_EQ_FREQUENCIES: [f32; 10] = [
+    31.0, 62.0, 125.0, 250.0, 500.0, 1000.0, 2000.0, 4000.0, 8000.0, 16000.0,
+];
+/// Bandwidth of the default bands, about an octave each.
+const DEFAULT_EQ_Q: f32 = 1.41;
+/// The level the limiter keeps peaks under, in dBFS.
+const LIMITER_THRESHOLD: f64 = -1.0;
+/// How long the limiter takes to recover after a peak, in milliseconds.
+const LIMITER_RELEASE: f64 = 100.0;
+/// The most the preamp and each band can boost or cut, in dB.
+const MAX_GAIN: f32 = 24.0;
+const MIN_FREQUENCY: f32 = 10.0;
+const MAX_FREQUENCY: f32 = 24000.0;
+const MIN_Q: f32 = 0.1;
+const MAX_Q: f32 = 10.0;
+
+/// Keeps a setting in range, using `default` for values that aren't finite, since NaN
+/// would get through every filter and the limiter and silence the output.
+fn clamp_setting(value: f32, min: f32, max: f32, default: f32) -> f32 {
+    if value.is_finite() {
+        value.clamp(min, max)
+    } else {
+        default
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum BandKind {
+    Peaking,
+    LowShelf,
+    HighShelf,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct EqBand {
+    pub kind: BandKind,
+    /// Centre frequency for peaking bands, or corner frequency for shelves, in Hz.
+    pub frequency: f32,
+    /// Gain in dB.
+    pub gain: f32,
+    pub q: f32,
+}
+
+/// A named set of equalizer bands, saved in the `eqpresets` setting.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct EqPreset {
+    /// Gain applied before the equalizer, in dB.
+    pub preamp: f32,
+    pub bands: Vec<EqBand>,
+}
+
+/// Settings for the DSP chain, saved in the `dsp` setting. Audio goes through the mono
+/// downmix, preamp, equalizer, balance and then the limiter, in that order.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase", default)]
+pub struct DspSettings {
+    /// Turns the preamp and equalizer on or off, keeping their settings.
+    pub eq_enabled: bool,
+    /// Gain applied before the equalizer, in dB.
+    pub preamp: f32,
+    pub bands: Vec<EqBand>,
+    /// Keeps peaks under -1 dBFS, so boosting doesn't clip.
+    pub limiter: bool,
+    /// From -1.0 for only the left channel to 1.0 for only the right channel.
+    pub balance: f32,
+    /// Mixes every channel down to mono, for listeners who can only hear on one side.
+    pub mono: bool,
+}
+
+impl Default for DspSettings {
+    fn default() -> Self {
+        Self {
+            eq_enabled: false,
+            preamp: 0.0,
+            bands: DEFAULT_EQ_FREQUENCIES
+                .iter()
+                .map(|&frequency| EqBand {
+                    kind: BandKind::Peaking,
+                    frequency,
+                    gain: 0.0,
+                    q: DEFAULT_EQ_Q,
+                })
+                .collect(),
+            limiter: true,
+            balance: 0.0,
+            mono: false,
+        }
+    }
+}
+
+impl DspSettings {
+    pub fn from_config(app: &AppHandle<Wry>) -> Self {
+        app.store(".app-config")
+            .ok()
+            .and_then(|store| store.get("dsp"))
+            .and_then(|settings| serde_json::from_value::<Self>(settings).ok())
+            .unwrap_or_default()
+            .sanitized()
+    }
+
+    /// Clamps the gains, frequencies, bandwidths and balance to the ranges the chain
+    /// handles, for settings edited by hand or sent by an out of date frontend.
+    pub fn sanitized(mut self) -> Self {
+        self.preamp = clamp_setting(self.preamp, -MAX_GAIN, MAX_GAIN, 0.0);
+        self.balance = clamp_setting(self.balance, -1.0, 1.0, 0.0);
+        for band in self.bands.iter_mut() {
+            band.frequency = clamp_setting(band.frequency, MIN_FREQUENCY, MAX_FREQUENCY, 1000.0);
+            band.gain = clamp_setting(band.gain, -MAX_GAIN, MAX_GAIN, 0.0);
+            band.q = clamp_setting(band.q, MIN_Q, MAX_Q, DEFAULT_EQ_Q);
+        }
+        self
+    }
+}
+
+/// Presets for the default bands, saved as the initial `eqpresets` setting.
+pub fn default_eq_presets() -> serde_json::Value {
+    let preset = |preamp: f32, gains: [f32; 10]| {
+        let bands: Vec<EqBand> = DEFAULT_EQ_FREQUENCIES
+            .iter()
+            .zip(gains)
+            .map(|(&frequency, gain)| EqBand {
+                kind: BandKind::Peaking,
+                frequency,
+                gain,
+                q: DEFAULT_EQ_Q,
+            })
+            .collect();
+        EqPreset { preamp, bands }
+    };
+    json!({
+        "Flat": preset(0.0, [0.0; 10]),
+        "Bass Boost": preset(-4.0, [5.0, 4.0, 3.0, 1.5, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0]),
+        "Treble Boost": preset(-4.0, [0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.5, 3.0, 4.0, 5.0]),
+        "Vocal": preset(-3.0, [-2.0, -2.0, -1.0, 0.0, 2.0, 3.0, 3.0, 2.0, 0.0, -1.0]),
+        "Loudness": preset(-4.0, [5.0, 3.5, 1.0, 0.0, -1.0, 0.0, 0.0, 1.0, 3.0, 4.0]),
+    })
+}
+
+fn db_to_gain(db: f64) -> f64 {
+    10f64.powf(db / 20.0)
+}
+
+/// A second order filter from the Audio EQ Cookbook, in transposed direct form II.
+struct Biquad {
+    b0: f64,
+    b1: f64,
+    b2: f64,
+    a1: f64,
+    a2: f64,
+    /// Filter state for each channel.
+    state: Vec<[f64; 2]>,
+}
+
+impl Biquad {
+    fn new(band: &EqBand, sample_rate: u32, channels: usize) -> Self {
+        let mut biquad = Self {
+            b0: 1.0,
+            b1: 0.0,
+            b2: 0.0,
+            a1: 0.0,
+            a2: 0.0,
+            state: vec![[0.0; 2]; channels],
+        };
+        biquad.set_band(band, sample_rate);
+        biquad
+    }
+
+    /// Updates the coefficients while keeping the filter state, so bands can be changed
+    /// while playing without clicks.
+    fn set_band(&mut self, band: &EqBand, sample_rate: u32) {
+        let gain = band.gain as f64;
+        // Frequencies above Nyquist can't be filtered
+        let frequency = (band.frequency as f64).clamp(10.0, sample_rate as f64 * 0.49);
+        let a = 10f64.powf(gain / 40.0);
+        let w0 = 2.0 * PI * frequency / sample_rate as f64;
+        let (sin, cos) = w0.sin_cos();
+        let alpha = sin / (2.0 * (band.q as f64).max(0.1));
+        let sqrt_a = a.sqrt();
+        let (b0, b1, b2, a0, a1, a2) = match band.kind {
+            BandKind::Peaking => (
+                1.0 + alpha * a,
+                -2.0 * cos,
+                1.0 - alpha * a,
+                1.0 + alpha / a,
+                -2.0 * cos,
+                1.0 - alpha / a,
+            ),
+            BandKind::LowShelf => (
+                a * ((a + 1.0) - (a - 1.0) * cos + 2.0 * sqrt_a * alpha),
+                2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+                a * ((a + 1.0) - (a - 1.0) * cos - 2.0 * sqrt_a * alpha),
+                (a + 1.0) + (a - 1.0) * cos + 2.0 * sqrt_a * alpha,
+                -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+                (a + 1.0) + (a - 1.0) * cos - 2.0 * sqrt_a * alpha,
+            ),
+            BandKind::HighShelf => (
+                a * ((a + 1.0) + (a - 1.0) * cos + 2.0 * sqrt_a * alpha),
+                -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+                a * ((a + 1.0) + (a - 1.0) * cos - 2.0 * sqrt_a * alpha),
+                (a + 1.0) - (a - 1.0) * cos + 2.0 * sqrt_a * alpha,
+                2.0 * ((a - 1.0) - (a + 1.0) * cos),
+                (a + 1.0) - (a - 1.0) * cos - 2.0 * sqrt_a * alpha,
+            ),
+        };
+        self.b0 = b0 / a0;
+        self.b1 = b1 / a0;
+        self.b2 = b2 / a0;
+        self.a1 = a1 / a0;
+        self.a2 = a2 / a0;
+    }
+
+    fn process(&mut self, samples: &mut [f32], channels: usize) {
+        for frame in samples.chunks_exact_mut(channels) {
+            for (sample, state) in frame.iter_mut().zip(self.state.iter_mut()) {
+                let input = *sample as f64;
+                let output = self.b0 * input + state[0];
+                state[0] = self.b1 * input - self.a1 * output + state[1];
+                state[1] = self.b2 * input - self.a2 * output;
+                *sample = output as f32;
+            }
+        }
+    }
+}
+
+/// Processes audio on the playback thread with the settings from [`DspSettings`].
+pub struct DspChain {
+    settings: DspSettings,
+    sample_rate: u32,
+    channels: usize,
+    filters: Vec<Biquad>,
+    limiter_gain: f64,
+    limiter_release: f64,
+}
+
+impl DspChain {
+    pub fn new(settings: DspSettings) -> Self {
+        Self {
+            settings,
+            sample_rate: 0,
+            channels: 0,
+            filters: Vec::new(),
+            limiter_gain: 1.0,
+            limiter_release: 0.0,
+        }
+    }
+
+    /// Applies new settings, keeping the filters' state when only their gains or
+    /// frequencies change.
+    pub fn set_settings(&mut self, settings: DspSettings) {
+        let same_bands = self.filters.len() == settings.bands.len();
+        self.settings = settings;
+        if same_bands && self.sample_rate > 0 {
+            for (filter, band) in self.filters.iter_mut().zip(self.settings.bands.iter()) {
+                filter.set_band(band, self.sample_rate);
+            }
+        } else {
+            self.filters.clear();
+            self.sample_rate = 0;
+        }
+    }
+
+    /// Sets up the filters for the format being processed.
+    fn prepare(&mut self, sample_rate: u32, channels: usize) {
+        if sample_rate == self.sample_rate && channels == self.channels {
+            return;
+        }
+        self.sample_rate = sample_rate;
+        self.channels = channels;
+        self.filters = self
+            .settings
+            .bands
+            .iter()
+            .map(|band| Biquad::new(band, sample_rate, channels))
+            .collect();
+        self.limiter_gain = 1.0;
+        self.limiter_release = 1.0 - (-1000.0 / (LIMITER_RELEASE * sample_rate as f64)).exp();
+    }
+
+    /// Processes interleaved samples in place.
+    pub fn process(&mut self, samples: &mut [f32], sample_rate: u32, channels: usize) {
+        self.prepare(sample_rate, channels);
+        if self.settings.mono && channels > 1 {
+            for frame in samples.chunks_exact_mut(channels) {
+                let mono = frame.iter().sum::<f32>() / channels as f32;
+                frame.fill(mono);
+            }
+        }
+        if self.settings.eq_enabled {
+            if self.settings.preamp != 0.0 {
+                let preamp = db_to_gain(self.settings.preamp as f64) as f32;
+                for sample in samples.iter_mut() {
+                    *sample *= preamp;
+                }
+            }
+            for filter in self.filters.iter_mut() {
+                filter.process(samples, channels);
+            }
+        }
+        let balance = self.settings.balance.clamp(-1.0, 1.0);
+        if balance != 0.0 && channels >= 2 {
+            let left = (1.0 - balance).min(1.0);
+            let right = (1.0 + balance).min(1.0);
+            for frame in samples.chunks_exact_mut(channels) {
+                frame[0] *= left;
+                frame[1] *= right;
+            }
+        }
+        if self.settings.limiter {
+            self.limit(samples, channels);
+        }
+    }
+
+    /// Turns the gain down as soon as a frame would go over the threshold, then lets it
+    /// recover slowly so the limiting isn't heard as distortion.
+    fn limit(&mut self, samples: &mut [f32], channels: usize) {
+        let threshold = db_to_gain(LIMITER_THRESHOLD);
+        for frame in samples.chunks_exact_mut(channels) {
+            let peak = frame.iter().fold(0.0f32, |peak, s| peak.max(s.abs())) as f64;
+            let target = if peak > threshold {
+                threshold / peak
+            } else {
+                1.0
+            };
+            if target < self.limiter_gain {
+                self.limiter_gain = target;
+            } else {
+                self.limiter_gain += (target - self.limiter_gain) * self.limiter_release;
+            }
+            if self.limiter_gain < 1.0 {
+                for sample in frame.iter_mut() {
+                    *sample = (*sample as f64 * self.limiter_gain) as f32;
+                }
+            }
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DspRenderSummary {
+    pub sample_rate: u32,
+    pub channels: usize,
+    pub duration: u64,
+    /// The highest sample level, in dBFS.
+    pub peak: Option<f64>,
+    /// Samples that would clip, at or above full scale.
+    pub clipped_samples: usize,
+}
+
+/// Decodes a whole file and runs it through the DSP chain the same way playback would,
+/// measuring the levels of each chunk as it goes rather than keeping the audio.
+fn render_file(path: &Path, settings: DspSettings) -> Result<DspRenderSummary, String> {
+    let mut decoder = AudioDecoder::open(path)?;
+    let sample_rate = decoder.sample_rate();
+    let channels = decoder.channels();
+    let mut chain = DspChain::new(settings.sanitized());
+    let mut samples = Vec::new();
+    let mut frames = 0;
+    let mut peak = 0.0f32;
+    let mut clipped_samples = 0;
+    while let Some(decoded) = decoder.next_samples()? {
+        samples.clear();
+        samples.extend_from_slice(decoded);
+        chain.process(&mut samples, sample_rate, channels);
+        frames += (samples.len() / channels.max(1)) as u64;
+        for sample in &samples {
+            peak = peak.max(sample.abs());
+            if sample.abs() >= 1.0 {
+                clipped_samples += 1;
+            }
+        }
+    }
+    Ok(DspRenderSummary {
+        sample_rate,
+        channels,
+        duration: frames * 1000 / sample_rate.max(1) as u64,
+        peak: (peak > 0.0).then(|| 20.0 * (peak as f64).log10()),
+        clipped_samples,
+    })
+}
+
+type EngineState<'a> = State<'a, Mutex<PlaybackEngine>>;
+
+#[tauri::command]
+pub fn get_dsp_settings(app: AppHandle) -> DspSettings {
+    DspSettings::from_config(&app)
+}
+
+/// Applies the DSP settings to playback straight away, so bands can be edited live, and
+/// saves them unless `save` is false.
+#[tauri::command]
+pub fn set_dsp_settings(
+    app: AppHandle,
+    state: EngineState,
+    settings: DspSettings,
+    save: Option<bool>,
+) -> Result<(), String> {
+    let settings = settings.sanitized();
+    if save.unwrap_or(true) {
+        let store = app.store(".app-config").map_err(|e| e.to_string())?;
+        store.set("dsp", json!(settings));
+        store.save().map_err(|e| e.to_string())?;
+    }
+    state.lock().unwrap().set_dsp_settings(&app, settings);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_eq_presets(app: AppHandle) -> BTreeMap<String, EqPreset> {
+    app.store(".app-config")
+        .ok()
+        .and_then(|store| store.get("eqpresets"))
+        .and_then(|presets| serde_json::from_value(presets).ok())
+        .unwrap_or_default()
+}
+
+/// Saves a preset, replacing any preset with the same name.
+#[tauri::command]
+pub fn save_eq_preset(app: AppHandle, name: String, preset: EqPreset) -> Result<(), String> {
+    let mut presets = get_eq_presets(app.clone());
+    presets.insert(name, preset);
+    let store = app.store(".app-config").map_err(|e| e.to_string())?;
+    store.set("eqpresets", json!(presets));
+    store.save().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn delete_eq_preset(app: AppHandle, name: String) -> Result<(), String> {
+    let mut presets = get_eq_presets(app.clone());
+    presets.remove(&name);
+    let store = app.store(".app-config").map_err(|e| e.to_string())?;
+    store.set("eqpresets", json!(presets));
+    store.save().map_err(|e| e.to_string())
+}
+
+/// Renders a file through the DSP chain offline and reports its levels, to check
+/// settings such as whether the limiter stops an equalizer boost from clipping.
+#[tauri::command]
+pub async fn render_dsp(
+    file_path: String,
+    settings: DspSettings,
+) -> Result<DspRenderSummary, String> {
+    tauri::async_runtime::spawn_blocking(move || render_file(Path::new(&file_path), settings))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 44100;
+
+    /// One second of a sine wave, the same on every channel.
+    fn sine(frequency: f32, amplitude: f32, channels: usize) -> Vec<f32> {
+        (0..SAMPLE_RATE)
+            .flat_map(|frame| {
+                let time = frame as f32 / SAMPLE_RATE as f32;
+                let sample = amplitude * (2.0 * std::f32::consts::PI * frequency * time).sin();
+                std::iter::repeat_n(sample, channels)
+            })
+            .collect()
+    }
+
+    /// Runs `samples` through the chain in chunks, like playback does.
+    fn render(settings: DspSettings, samples: &[f32], channels: usize) -> Vec<f32> {
+        let mut chain = DspChain::new(settings);
+        let mut output = samples.to_vec();
+        for chunk in output.chunks_mut(1024 * channels) {
+            chain.process(chunk, SAMPLE_RATE, channels);
+        }
+        output
+    }
+
+    fn peak(samples: &[f32]) -> f32 {
+        samples
+            .iter()
+            .fold(0.0, |peak, sample| peak.max(sample.abs()))
+    }
+
+    #[test]
+    fn flat_equalizer_leaves_audio_unchanged() {
+        let input = sine(440.0, 0.5, 2);
+        let settings = DspSettings {
+            eq_enabled: true,
+            ..Default::default()
+        };
+        let output = render(settings, &input, 2);
+        let difference = input
+            .iter()
+            .zip(&output)
+            .fold(0.0f32, |max, (a, b)| max.max((a - b).abs()));
+        assert!(difference < 1e-5, "differs by {}", difference);
+    }
+
+    #[test]
+    fn limiter_keeps_boosted_peaks_under_threshold() {
+        let input = sine(1000.0, 0.9, 2);
+        let settings = DspSettings {
+            eq_enabled: true,
+            preamp: 12.0,
+            ..Default::default()
+        };
+        let output = render(settings.clone(), &input, 2);
+        assert!(peak(&output) <= db_to_gain(LIMITER_THRESHOLD) as f32 + 1e-6);
+
+        let unlimited = render(
+            DspSettings {
+                limiter: false,
+                ..settings
+            },
+            &input,
+            2,
+        );
+        assert!(peak(&unlimited) > 3.0);
+    }
+
+    /// The level of a mono signal in dB, skipping the first 100 ms while the filters settle.
+    fn level(samples: &[f32]) -> f64 {
+        let settled = &samples[SAMPLE_RATE as usize / 10..];
+        let power = settled
+            .iter()
+            .map(|&sample| (sample as f64).powi(2))
+            .sum::<f64>()
+            / settled.len() as f64;
+        10.0 * power.log10()
+    }
+
+    fn gain_at(settings: &DspSettings, frequency: f32) -> f64 {
+        let input = sine(frequency, 0.25, 1);
+        let output = render(settings.clone(), &input, 1);
+        level(&output) - level(&input)
+    }
+
+    fn single_band(kind: BandKind, frequency: f32, gain: f32, q: f32) -> DspSettings {
+        DspSettings {
+            eq_enabled: true,
+            bands: vec![EqBand {
+                kind,
+                frequency,
+                gain,
+                q,
+            }],
+            limiter: false,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn peaking_band_boosts_only_around_its_frequency() {
+        let settings = single_band(BandKind::Peaking, 1000.0, 6.0, DEFAULT_EQ_Q);
+        let boost = gain_at(&settings, 1000.0);
+        assert!((boost - 6.0).abs() < 0.1, "boosted by {} dB", boost);
+        let unchanged = gain_at(&settings, 100.0);
+        assert!(unchanged.abs() < 0.25, "changed by {} dB", unchanged);
+    }
+
+    #[test]
+    fn low_shelf_boosts_below_its_corner() {
+        let settings = single_band(BandKind::LowShelf, 200.0, 6.0, 0.707);
+        let boost = gain_at(&settings, 30.0);
+        assert!((boost - 6.0).abs() < 0.3, "boosted by {} dB", boost);
+        let unchanged = gain_at(&settings, 5000.0);
+        assert!(unchanged.abs() < 0.1, "changed by {} dB", unchanged);
+    }
+
+    #[test]
+    fn sanitized_settings_are_finite_and_in_range() {
+        let settings = DspSettings {
+            preamp: f32::NAN,
+            balance: 3.0,
+            bands: vec![
+                EqBand {
+                    kind: BandKind::Peaking,
+                    frequency: f32::INFINITY,
+                    gain: 100.0,
+                    q: 0.0,
+                },
+                EqBand {
+                    kind: BandKind::HighShelf,
+                    frequency: 1.0,
+                    gain: f32::NEG_INFINITY,
+                    q: f32::NAN,
+                },
+            ],
+            ..Default::default()
+        }
+        .sanitized();
+        assert_eq!(settings.preamp, 0.0);
+        assert_eq!(settings.balance, 1.0);
+        assert_eq!(settings.bands[0].frequency, 1000.0);
+        assert_eq!(settings.bands[0].gain, MAX_GAIN);
+        assert_eq!(settings.bands[0].q, MIN_Q);
+        assert_eq!(settings.bands[1].frequency, MIN_FREQUENCY);
+        assert_eq!(settings.bands[1].gain, 0.0);
+        assert_eq!(settings.bands[1].q, DEFAULT_EQ_Q);
+
+        let output = render(
+            DspSettings {
+                eq_enabled: true,
+                ..settings
+            },
+            &sine(440.0, 0.5, 2),
+            2,
+        );
+        assert!(output.iter().all(|sample| sample.is_finite()));
+    }
+
+    #[test]
+    fn mono_mixes_channels_together() {
+        let left = sine(440.0, 0.5, 1);
+        let input: Vec<f32> = left.iter().flat_map(|&sample| [sample, 0.0]).collect();
+        let settings = DspSettings {
+            mono: true,
+            ..Default::default()
+        };
+        let output = render(settings, &input, 2);
+        for (frame, &sample) in output.chunks_exact(2).zip(&left) {
+            assert_eq!(frame[0], frame[1]);
+            assert!((frame[0] - sample / 2.0).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn balance_turns_down_the_other_channel() {
+        let input = sine(440.0, 0.5, 2);
+        let settings = DspSettings {
+            balance: 0.5,
+            ..Default::default()
+        };
+        let output = render(settings, &input, 2);
+        for (frame, input_frame) in output.chunks_exact(2).zip(input.chunks_exact(2)) {
+            assert!((frame[0] - input_frame[0] * 0.5).abs() < 1e-6);
+            assert_eq!(frame[1], input_frame[1]);
+        }
+
+        let settings = DspSettings {
+            balance: -1.0,
+            ..Default::default()
+        };
+        let output = render(settings, &input, 2);
+        assert_eq!(
+            peak(
+                &output
+                    .iter()
+                    .skip(1)
+                    .step_by(2)
+                    .copied()
+                    .collect::<Vec<_>>()
+            ),
+            0.0
+        );
+        assert_eq!(peak(&output), peak(&input));
+    }
+}
@@ -0,0 +1,94 @@
+use super::track_metadata::TrackMetadata;
+use lofty::prelude::{Accessor, ItemKey};
+use lofty::tag::Tag;
+
+fn get_text(tag: &Tag, key: ItemKey) -> Option<String> {
+    tag.get_string(key)
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(str::to_string)
+}
+
+fn get_list(tag: &Tag, key: ItemKey) -> Vec<String> {
+    tag.get_strings(key).map(str::to_string).collect()
+}
+
+/// Whole number fields, which some taggers write with surrounding whitespace or leading zeros.
+fn get_number(tag: &Tag, key: ItemKey) -> Option<u32> {
+    tag.get_string(key)
+        .and_then(|value| value.trim().parse::<u32>().ok())
+}
+
+fn parse_flag(value: &str) -> Option<bool> {
+    match value.trim().to_lowercase().as_str() {
+        "1" | "true" | "yes" => Some(true),
+        "0" | "false" | "no" | "" => Some(false),
+        _ => None,
+    }
+}
+
+/// iTunes advisory values are 1 (or the older 4) for explicit, 2 for clean and 0 for none.
+fn parse_advisory(value: &str) -> Option<&'static str> {
+    match value.trim() {
+        "1" | "4" => Some("explicit"),
+        "2" => Some("clean"),
+        "0" => Some("none"),
+        _ => None,
+    }
+}
+
+/// Sets the descriptive fields beyond the basic title, artist and album ones. lofty maps
+/// each `ItemKey` from ID3v2, Vorbis comments, MP4 and APE, so this works the same for
+/// every format that stores the field.
+pub fn set_extended_tags(metadata: &mut TrackMetadata, tag: &Tag) {
+    metadata.music_brainz_recording_id = get_text(tag, ItemKey::MusicBrainzRecordingId);
+    metadata.music_brainz_track_id = get_text(tag, ItemKey::MusicBrainzTrackId);
+    metadata.music_brainz_release_id = get_text(tag, ItemKey::MusicBrainzReleaseId);
+    metadata.music_brainz_release_group_id = get_text(tag, ItemKey::MusicBrainzReleaseGroupId);
+    metadata.music_brainz_work_id = get_text(tag, ItemKey::MusicBrainzWorkId);
+    metadata.music_brainz_release_type = get_text(tag, ItemKey::MusicBrainzReleaseType);
+    metadata.music_brainz_artist_id = get_list(tag, ItemKey::MusicBrainzArtistId);
+    metadata.music_brainz_release_artist_id = get_list(tag, ItemKey::MusicBrainzReleaseArtistId);
+    metadata.title_sort = get_text(tag, ItemKey::TrackTitleSortOrder);
+    metadata.artist_sort = get_text(tag, ItemKey::TrackArtistSortOrder);
+    metadata.album_sort = get_text(tag, ItemKey::AlbumTitleSortOrder);
+    metadata.album_artist_sort = get_text(tag, ItemKey::AlbumArtistSortOrder);
+    metadata.composer_sort = get_text(tag, ItemKey::ComposerSortOrder);
+    metadata.subtitle = get_text(tag, ItemKey::TrackSubtitle);
+    metadata.initial_key = get_text(tag, ItemKey::InitialKey);
+    metadata.isrc = get_text(tag, ItemKey::Isrc);
+    metadata.barcode = get_text(tag, ItemKey::Barcode);
+    metadata.label = get_text(tag, ItemKey::Label);
+    metadata.catalog_number = get_text(tag, ItemKey::CatalogNumber);
+    metadata.work = get_text(tag, ItemKey::Work);
+    metadata.movement = get_text(tag, ItemKey::Movement);
+    metadata.movement_number = get_number(tag, ItemKey::MovementNumber);
+    metadata.movement_total = get_number(tag, ItemKey::MovementTotal);
+    metadata.original_release_date = get_text(tag, ItemKey::OriginalReleaseDate);
+    metadata.release_country = get_text(tag, ItemKey::ReleaseCountry);
+    metadata.copyright = get_text(tag, ItemKey::CopyrightMessage);
+    metadata.conductor = get_list(tag, ItemKey::Conductor);
+    metadata.lyricist = get_list(tag, ItemKey::Lyricist);
+    metadata.arranger = get_list(tag, ItemKey::Arranger);
+    metadata.producer = get_list(tag, ItemKey::Producer);
+    metadata.remixer = get_list(tag, ItemKey::Remixer);
+    metadata.mood = get_list(tag, ItemKey::Mood);
+
+    // Decimal BPM is only stored by some formats, the rest only have the whole number
+    metadata.bpm = tag
+        .get_string(ItemKey::Bpm)
+        .or_else(|| tag.get_string(ItemKey::IntegerBpm))
+        .and_then(|value| value.trim().parse::<f64>().ok())
+        .filter(|bpm| *bpm > 0.0);
+    metadata.grouping = get_text(tag, ItemKey::ContentGroup)
+        .or_else(|| get_text(tag, ItemKey::AppleId3v2ContentGroup));
+    metadata.compilation = tag
+        .get_string(ItemKey::FlagCompilation)
+        .and_then(parse_flag);
+    metadata.advisory = tag
+        .get_string(ItemKey::ParentalAdvisory)
+        .and_then(parse_advisory)
+        .map(str::to_string);
+    metadata.track_total = tag.track_total();
+    metadata.disc_total = tag.disk_total();
+}
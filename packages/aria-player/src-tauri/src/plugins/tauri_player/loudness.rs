@@ -0,0 +1,340 @@
+use super::crossfade::is_same_album;
+use super::decoder::AudioDecoder;
+use super::get_playback_metadata;
+use super::metadata_batch::{WorkerPermit, MAX_WORKERS};
+use super::tag_editor::{read_primary_tag, save_tag_atomically};
+use super::track_metadata::TrackMetadata;
+use ebur128::{Channel, EbuR128, Mode};
+use lofty::config::ParseOptions;
+use lofty::file::{AudioFile, FileType};
+use lofty::ogg::OpusFile;
+use lofty::prelude::ItemKey;
+use lofty::tag::Tag;
+use serde::Serialize;
+use std::fs::File;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+/// ReplayGain 2.0 reference level, in LUFS.
+const REPLAYGAIN_REFERENCE_LOUDNESS: f64 = -18.0;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrackLoudness {
+    pub file_path: String,
+    /// Integrated loudness in LUFS.
+    pub loudness: Option<f64>,
+    /// ReplayGain track gain in dB.
+    pub gain: Option<f64>,
+    /// Highest sample peak across all channels, where 1.0 is full scale.
+    pub peak: Option<f64>,
+    /// The gain and peak of the album the track was analyzed with, if it has one.
+    pub album_gain: Option<f64>,
+    pub album_peak: Option<f64>,
+    pub error: Option<String>,
+}
+
+/// Tracks from the same album, going by their album and album artist.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AlbumLoudness {
+    pub album: Option<String>,
+    pub album_artist: Option<String>,
+    pub file_paths: Vec<String>,
+    pub loudness: Option<f64>,
+    pub gain: Option<f64>,
+    pub peak: Option<f64>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoudnessAnalysis {
+    pub tracks: Vec<TrackLoudness>,
+    pub albums: Vec<AlbumLoudness>,
+}
+
+fn parse_gain(value: &str) -> Option<f64> {
+    let value = value.trim();
+    let value = value
+        .strip_suffix("dB")
+        .or_else(|| value.strip_suffix("db"))
+        .unwrap_or(value);
+    value.trim().parse().ok()
+}
+
+/// R128 gains are stored as Q7.8 fixed point integers.
+fn parse_r128_gain(value: &str) -> Option<f64> {
+    value
+        .trim()
+        .parse::<i16>()
+        .ok()
+        .map(|gain| gain as f64 / 256.0)
+}
+
+/// Only Opus defines R128 gain tags, and lofty's generic tag drops them, so they are
+/// read from the file's Vorbis comments directly.
+fn read_r128_gains(path: &Path) -> (Option<f64>, Option<f64>) {
+    let Ok(mut file) = File::open(path) else {
+        return (None, None);
+    };
+    let parse_options = ParseOptions::new()
+        .read_properties(false)
+        .read_cover_art(false);
+    let Ok(opus_file) = OpusFile::read_from(&mut file, parse_options) else {
+        return (None, None);
+    };
+    let comments = opus_file.vorbis_comments();
+    (
+        comments.get("R128_TRACK_GAIN").and_then(parse_r128_gain),
+        comments.get("R128_ALBUM_GAIN").and_then(parse_r128_gain),
+    )
+}
+
+/// Sets the file's ReplayGain gains and peaks, along with Opus R128 gains.
+pub fn set_loudness_metadata(
+    metadata: &mut TrackMetadata,
+    path: &Path,
+    file_type: FileType,
+    tag: Option<&Tag>,
+) {
+    if let Some(tag) = tag {
+        let get_peak = |key| {
+            tag.get_string(key)
+                .and_then(|value| value.trim().parse::<f64>().ok())
+        };
+        metadata.replay_gain_track_gain = tag
+            .get_string(ItemKey::ReplayGainTrackGain)
+            .and_then(parse_gain);
+        metadata.replay_gain_album_gain = tag
+            .get_string(ItemKey::ReplayGainAlbumGain)
+            .and_then(parse_gain);
+        metadata.replay_gain_track_peak = get_peak(ItemKey::ReplayGainTrackPeak);
+        metadata.replay_gain_album_peak = get_peak(ItemKey::ReplayGainAlbumPeak);
+    }
+    if file_type == FileType::Opus {
+        (metadata.r128_track_gain, metadata.r128_album_gain) = read_r128_gains(path);
+    }
+}
+
+fn measure_file(path: &Path) -> Result<EbuR128, String> {
+    let mut decoder = AudioDecoder::open(path)?;
+    let mut meter = EbuR128::new(
+        decoder.channels() as u32,
+        decoder.sample_rate(),
+        Mode::I | Mode::SAMPLE_PEAK,
+    )
+    .map_err(|e| e.to_string())?;
+    if decoder.channels() == 1 {
+        // ReplayGain treats mono as if it were played through both stereo speakers
+        meter
+            .set_channel(0, Channel::DualMono)
+            .map_err(|e| e.to_string())?;
+    }
+    while let Some(samples) = decoder.next_samples()? {
+        meter.add_frames_f32(samples).map_err(|e| e.to_string())?;
+    }
+    Ok(meter)
+}
+
+fn get_peak(meter: &EbuR128) -> Option<f64> {
+    (0..meter.channels())
+        .filter_map(|channel| meter.sample_peak(channel).ok())
+        .reduce(f64::max)
+}
+
+/// Silent tracks have no measurable loudness, and so no gain.
+fn get_gain(loudness: f64) -> Option<f64> {
+    loudness
+        .is_finite()
+        .then_some(REPLAYGAIN_REFERENCE_LOUDNESS - loudness)
+}
+
+fn write_replaygain_tags(
+    path: &Path,
+    track: &TrackLoudness,
+    preserve_date_modified: bool,
+) -> Result<(), String> {
+    let mut tag = read_primary_tag(path)?;
+    let values = [
+        (ItemKey::ReplayGainTrackGain, track.gain, true),
+        (ItemKey::ReplayGainTrackPeak, track.peak, false),
+        (ItemKey::ReplayGainAlbumGain, track.album_gain, true),
+        (ItemKey::ReplayGainAlbumPeak, track.album_peak, false),
+    ];
+    for (key, value, is_gain) in values {
+        let Some(value) = value else {
+            continue;
+        };
+        let text = if is_gain {
+            format!("{:.2} dB", value)
+        } else {
+            format!("{:.6}", value)
+        };
+        tag.insert_text(key, text);
+    }
+    save_tag_atomically(path, &tag, preserve_date_modified)
+}
+
+/// Measures the EBU R128 loudness of each file, and of each album among them, and returns
+/// the ReplayGain 2.0 gains and peaks. Files are grouped into albums the same way as for
+/// crossfading, and files without an album only get track gains. With `write_tags` set,
+/// the results are also written to the files as ReplayGain tags.
+#[tauri::command]
+pub async fn analyze_loudness(
+    file_paths: Vec<String>,
+    write_tags: Option<bool>,
+    preserve_date_modified: Option<bool>,
+) -> Result<LoudnessAnalysis, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        analyze_files(
+            file_paths,
+            write_tags.unwrap_or(false),
+            preserve_date_modified.unwrap_or(false),
+        )
+    })
+    .await
+    .map_err(|e| e.to_string())
+}
+
+/// An album's loudness, measured across all of its tracks.
+fn measure_album(meters: &[&EbuR128]) -> (Option<f64>, Option<f64>, Option<f64>) {
+    let loudness = EbuR128::loudness_global_multiple(meters.iter().copied()).ok();
+    let peak = meters
+        .iter()
+        .filter_map(|meter| get_peak(meter))
+        .reduce(f64::max);
+    (loudness, loudness.and_then(get_gain), peak)
+}
+
+fn analyze_files(
+    file_paths: Vec<String>,
+    write_tags: bool,
+    preserve_date_modified: bool,
+) -> LoudnessAnalysis {
+    let next_index = AtomicUsize::new(0);
+    type Measurement = (Result<EbuR128, String>, Option<TrackMetadata>);
+    let measurements: Mutex<Vec<Option<Measurement>>> =
+        Mutex::new((0..file_paths.len()).map(|_| None).collect());
+    let workers = thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+        .clamp(1, MAX_WORKERS)
+        .min(file_paths.len().max(1));
+
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| loop {
+                let _permit = WorkerPermit::acquire();
+                let index = next_index.fetch_add(1, Ordering::Relaxed);
+                let Some(file_path) = file_paths.get(index) else {
+                    break;
+                };
+                let meter = measure_file(Path::new(file_path));
+                let metadata = get_playback_metadata(file_path).ok();
+                measurements.lock().unwrap()[index] = Some((meter, metadata));
+            });
+        }
+    });
+
+    let measurements: Vec<Measurement> = measurements
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(|measurement| {
+            measurement.unwrap_or_else(|| (Err("File was not analyzed".to_string()), None))
+        })
+        .collect();
+
+    let mut tracks: Vec<TrackLoudness> = file_paths
+        .iter()
+        .zip(&measurements)
+        .map(|(file_path, (meter, _))| match meter {
+            Ok(meter) => {
+                let loudness = meter.loudness_global().ok();
+                TrackLoudness {
+                    file_path: file_path.clone(),
+                    loudness,
+                    gain: loudness.and_then(get_gain),
+                    peak: get_peak(meter),
+                    album_gain: None,
+                    album_peak: None,
+                    error: None,
+                }
+            }
+            Err(error) => TrackLoudness {
+                file_path: file_path.clone(),
+                loudness: None,
+                gain: None,
+                peak: None,
+                album_gain: None,
+                album_peak: None,
+                error: Some(error.clone()),
+            },
+        })
+        .collect();
+
+    let mut album_tracks: Vec<Vec<usize>> = Vec::new();
+    for (index, measurement) in measurements.iter().enumerate() {
+        let (Ok(_), Some(metadata)) = measurement else {
+            continue;
+        };
+        if metadata.album.is_none() {
+            continue;
+        }
+        let album = album_tracks.iter_mut().find(|indices| {
+            measurements[indices[0]]
+                .1
+                .as_ref()
+                .is_some_and(|first| is_same_album(first, metadata))
+        });
+        match album {
+            Some(indices) => indices.push(index),
+            None => album_tracks.push(vec![index]),
+        }
+    }
+    let albums: Vec<AlbumLoudness> = album_tracks
+        .into_iter()
+        .map(|indices| {
+            let meters: Vec<&EbuR128> = indices
+                .iter()
+                .filter_map(|&index| measurements[index].0.as_ref().ok())
+                .collect();
+            let (loudness, gain, peak) = measure_album(&meters);
+            for &index in &indices {
+                tracks[index].album_gain = gain;
+                tracks[index].album_peak = peak;
+            }
+            let metadata = measurements[indices[0]].1.as_ref();
+            AlbumLoudness {
+                album: metadata.and_then(|metadata| metadata.album.clone()),
+                album_artist: metadata.and_then(|metadata| {
+                    metadata
+                        .album_artist
+                        .clone()
+                        .or_else(|| metadata.artist.first().cloned())
+                }),
+                file_paths: indices
+                    .iter()
+                    .map(|&index| file_paths[index].clone())
+                    .collect(),
+                loudness,
+                gain,
+                peak,
+            }
+        })
+        .collect();
+
+    if write_tags {
+        for track in tracks.iter_mut().filter(|track| track.error.is_none()) {
+            if let Err(error) =
+                write_replaygain_tags(Path::new(&track.file_path), track, preserve_date_modified)
+            {
+                track.error = Some(error);
+            }
+        }
+    }
+
+    LoudnessAnalysis { tracks, albums }
+}
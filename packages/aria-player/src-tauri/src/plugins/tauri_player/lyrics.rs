@@ -0,0 +1,283 @@
+use super::{decode_text, is_placeholder_file};
+use lofty::aac::AacFile;
+use lofty::config::ParseOptions;
+use lofty::file::{AudioFile, FileType, TaggedFileExt};
+use lofty::id3::v2::{
+    Frame, Id3v2Tag, SyncTextContentType, SynchronizedTextFrame, TimestampFormat,
+};
+use lofty::iff::aiff::AiffFile;
+use lofty::iff::wav::WavFile;
+use lofty::mpeg::MpegFile;
+use lofty::prelude::ItemKey;
+use lofty::probe::Probe;
+use serde::Serialize;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum LyricsSource {
+    /// A `.lrc` file next to the audio file
+    Sidecar,
+    /// An ID3v2 SYLT frame
+    SynchronizedFrame,
+    /// A USLT frame, or a `LYRICS` field in other tag formats
+    Tag,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LyricsLine {
+    /// Time in milliseconds from the start of the track, with any offset already applied.
+    pub time: u64,
+    pub text: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Lyrics {
+    pub source: LyricsSource,
+    pub synced: bool,
+    /// The lyrics as plain text, without timestamps.
+    pub text: String,
+    /// Timestamped lines sorted by time, empty if the lyrics aren't synced.
+    pub lines: Vec<LyricsLine>,
+}
+
+impl Lyrics {
+    fn from_text(source: LyricsSource, text: &str) -> Option<Self> {
+        if let Some(lines) = parse_lrc(text) {
+            return Some(Self::from_lines(source, lines));
+        }
+        let text = text.trim();
+        (!text.is_empty()).then(|| Self {
+            source,
+            synced: false,
+            text: text.to_string(),
+            lines: Vec::new(),
+        })
+    }
+
+    fn from_lines(source: LyricsSource, lines: Vec<LyricsLine>) -> Self {
+        Self {
+            source,
+            synced: true,
+            text: lines
+                .iter()
+                .map(|line| line.text.as_str())
+                .collect::<Vec<_>>()
+                .join("\n"),
+            lines,
+        }
+    }
+}
+
+/// Parses an LRC timestamp such as `01:23`, `01:23.45` or `01:23.456` into milliseconds.
+fn parse_timestamp(value: &str) -> Option<u64> {
+    let (minutes, rest) = value.split_once(':')?;
+    let (seconds, fraction) = match rest.split_once(['.', ':']) {
+        Some((seconds, fraction)) => (seconds, fraction),
+        None => (rest, ""),
+    };
+    let minutes: u64 = minutes.trim().parse().ok()?;
+    let seconds: u64 = seconds.trim().parse().ok()?;
+    if seconds >= 60 || !fraction.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    // Fractions are hundredths in most files, but any number of digits is accepted
+    let milliseconds = format!("{:0<3}", fraction)[..3].parse::<u64>().ok()?;
+    Some((minutes * 60 + seconds) * 1000 + milliseconds)
+}
+
+/// Removes enhanced LRC word timestamps, such as `<00:12.34>`, from a line.
+fn strip_word_timestamps(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('<') {
+        let Some(end) = rest[start..].find('>') else {
+            break;
+        };
+        let inner = &rest[start + 1..start + end];
+        result.push_str(&rest[..start]);
+        if parse_timestamp(inner).is_none() {
+            result.push_str(&rest[start..=start + end]);
+        }
+        rest = &rest[start + end + 1..];
+    }
+    result.push_str(rest);
+    result.trim().to_string()
+}
+
+/// Parses LRC formatted lyrics, returning `None` if the text has no timestamped lines.
+///
+/// A line can have several timestamps when it's repeated. The `[offset:]` tag is in
+/// milliseconds, and a positive offset makes every line appear earlier.
+pub fn parse_lrc(text: &str) -> Option<Vec<LyricsLine>> {
+    let mut offset: i64 = 0;
+    let mut lines = Vec::new();
+    for line in text.lines() {
+        let mut rest = line.trim();
+        let mut times = Vec::new();
+        while let Some(tag_end) = rest.strip_prefix('[').and_then(|tag| tag.find(']')) {
+            let tag = &rest[1..=tag_end];
+            rest = &rest[tag_end + 2..];
+            if let Some(time) = parse_timestamp(tag) {
+                times.push(time);
+            } else if let Some((key, value)) = tag.split_once(':') {
+                if key.trim().eq_ignore_ascii_case("offset") {
+                    offset = value.trim().parse().unwrap_or(0);
+                }
+            }
+        }
+        let text = strip_word_timestamps(rest);
+        lines.extend(times.into_iter().map(|time| (time, text.clone())));
+    }
+    if lines.is_empty() {
+        return None;
+    }
+    let mut lines: Vec<LyricsLine> = lines
+        .into_iter()
+        .map(|(time, text)| LyricsLine {
+            time: (time as i64 - offset).max(0) as u64,
+            text,
+        })
+        .collect();
+    lines.sort_by_key(|line| line.time);
+    Some(lines)
+}
+
+fn get_sidecar_path(path: &Path) -> Option<PathBuf> {
+    ["lrc", "LRC"]
+        .iter()
+        .map(|extension| path.with_extension(extension))
+        .find(|sidecar_path| sidecar_path.is_file())
+}
+
+fn read_sidecar_lyrics(path: &Path) -> Option<Lyrics> {
+    let contents = fs::read(get_sidecar_path(path)?).ok()?;
+    Lyrics::from_text(LyricsSource::Sidecar, &decode_text(&contents))
+}
+
+/// lofty's generic tag drops SYLT frames, so the ID3v2 tag is read directly for formats that use it.
+fn read_id3v2_tag(path: &Path, file_type: FileType) -> Option<Id3v2Tag> {
+    let mut file = File::open(path).ok()?;
+    let options = ParseOptions::new()
+        .read_properties(false)
+        .read_cover_art(false);
+    match file_type {
+        FileType::Mpeg => MpegFile::read_from(&mut file, options).ok()?.remove_id3v2(),
+        FileType::Aac => AacFile::read_from(&mut file, options).ok()?.remove_id3v2(),
+        FileType::Aiff => AiffFile::read_from(&mut file, options).ok()?.remove_id3v2(),
+        FileType::Wav => WavFile::read_from(&mut file, options).ok()?.remove_id3v2(),
+        _ => None,
+    }
+}
+
+fn read_synchronized_lyrics(path: &Path, file_type: FileType) -> Option<Lyrics> {
+    let tag = read_id3v2_tag(path, file_type)?;
+    let frame = tag
+        .into_iter()
+        .filter_map(|frame| match frame {
+            Frame::Binary(binary) if binary.id().as_str() == "SYLT" => {
+                SynchronizedTextFrame::parse(&binary.data, binary.flags()).ok()
+            }
+            _ => None,
+        })
+        // Timestamps counted in MPEG frames would need the file's frame size, and are very rare
+        .filter(|frame| frame.timestamp_format == TimestampFormat::MS)
+        .min_by_key(|frame| frame.content_type != SyncTextContentType::Lyrics)?;
+    let lines: Vec<LyricsLine> = frame
+        .content
+        .into_iter()
+        .map(|(time, text)| LyricsLine {
+            time: time as u64,
+            text: text.trim_matches(['\r', '\n']).to_string(),
+        })
+        .collect();
+    (!lines.is_empty()).then(|| Lyrics::from_lines(LyricsSource::SynchronizedFrame, lines))
+}
+
+/// Reads the lyrics for a file, preferring synced lyrics from a sidecar `.lrc` file, then a
+/// SYLT frame, then LRC text stored in the tag, before falling back to unsynced lyrics.
+#[tauri::command]
+pub fn get_lyrics(file_path: String) -> Result<Option<Lyrics>, String> {
+    let path = Path::new(&file_path);
+    if is_placeholder_file(path) {
+        return Err("placeholder_file".to_string());
+    }
+    let tagged_file = match Probe::open(path) {
+        Ok(file) => file.read().map_err(|e| e.to_string())?,
+        Err(_) => return Err("Failed to probe the file".to_string()),
+    };
+
+    let sidecar_lyrics = read_sidecar_lyrics(path);
+    if sidecar_lyrics.as_ref().is_some_and(|lyrics| lyrics.synced) {
+        return Ok(sidecar_lyrics);
+    }
+    if let Some(lyrics) = read_synchronized_lyrics(path, tagged_file.file_type()) {
+        return Ok(Some(lyrics));
+    }
+    let tag_lyrics = tagged_file.primary_tag().and_then(|tag| {
+        tag.get_string(ItemKey::Lyrics)
+            .or_else(|| tag.get_string(ItemKey::UnsyncLyrics))
+            .and_then(|text| Lyrics::from_text(LyricsSource::Tag, text))
+    });
+    if tag_lyrics.as_ref().is_some_and(|lyrics| lyrics.synced) {
+        return Ok(tag_lyrics);
+    }
+    Ok(sidecar_lyrics.or(tag_lyrics))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn times(lines: &[LyricsLine]) -> Vec<(u64, &str)> {
+        lines
+            .iter()
+            .map(|line| (line.time, line.text.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn parses_timestamps() {
+        assert_eq!(parse_timestamp("01:23"), Some(83_000));
+        assert_eq!(parse_timestamp("01:23.4"), Some(83_400));
+        assert_eq!(parse_timestamp("01:23.45"), Some(83_450));
+        assert_eq!(parse_timestamp("01:23.456"), Some(83_456));
+        assert_eq!(parse_timestamp("01:23:45"), Some(83_450));
+        assert_eq!(parse_timestamp("01:60.00"), None);
+        assert_eq!(parse_timestamp("ar:Artist"), None);
+    }
+
+    #[test]
+    fn repeats_lines_with_several_timestamps() {
+        let lines = parse_lrc("[00:10.00][00:30.00]Chorus\n[00:20.00]Verse").unwrap();
+        assert_eq!(
+            times(&lines),
+            vec![(10_000, "Chorus"), (20_000, "Verse"), (30_000, "Chorus")]
+        );
+    }
+
+    #[test]
+    fn applies_offset_to_every_line() {
+        let lines = parse_lrc("[00:01.00]One\n[offset:+500]\n[00:00.20]Zero").unwrap();
+        assert_eq!(times(&lines), vec![(0, "Zero"), (500, "One")]);
+        let lines = parse_lrc("[offset:-250]\n[00:01.00]One").unwrap();
+        assert_eq!(times(&lines), vec![(1_250, "One")]);
+    }
+
+    #[test]
+    fn strips_word_timestamps_and_skips_id_tags() {
+        let lines = parse_lrc("[ar:Artist]\n[ti:Title]\n[00:05.00]<00:05.00>Hello <00:05.50>world")
+            .unwrap();
+        assert_eq!(times(&lines), vec![(5_000, "Hello world")]);
+        assert_eq!(strip_word_timestamps("a <b> c"), "a <b> c");
+    }
+
+    #[test]
+    fn plain_text_is_not_lrc() {
+        assert!(parse_lrc("Some lyrics\n[Chorus]\nMore lyrics").is_none());
+        assert!(parse_lrc("").is_none());
+    }
+}
@@ -0,0 +1,170 @@
+use super::get_metadata;
+use super::track_metadata::TrackMetadata;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use tauri::ipc::Channel;
+use tauri::{AppHandle, Manager, State};
+
+/// The most threads a batch of files is read with.
+pub const MAX_WORKERS: usize = 8;
+
+/// Files being worked on by batches, shared with other batch work such as loudness
+/// analysis, so running several at once doesn't multiply the threads reading files.
+static ACTIVE_WORKERS: Mutex<usize> = Mutex::new(0);
+static WORKER_RELEASED: Condvar = Condvar::new();
+
+/// One of the `MAX_WORKERS` slots shared by all batch work, released when dropped.
+pub struct WorkerPermit;
+
+impl WorkerPermit {
+    /// Waits until fewer than `MAX_WORKERS` files are being worked on.
+    pub fn acquire() -> Self {
+        let mut active = ACTIVE_WORKERS.lock().unwrap();
+        while *active >= MAX_WORKERS {
+            active = WORKER_RELEASED.wait(active).unwrap();
+        }
+        *active += 1;
+        Self
+    }
+}
+
+impl Drop for WorkerPermit {
+    fn drop(&mut self) {
+        *ACTIVE_WORKERS.lock().unwrap() -= 1;
+        WORKER_RELEASED.notify_one();
+    }
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase", tag = "event", content = "data")]
+pub enum MetadataBatchEvent {
+    #[serde(rename_all = "camelCase")]
+    Item {
+        file_path: String,
+        metadata: Option<Box<TrackMetadata>>,
+        error: Option<String>,
+    },
+    #[serde(rename_all = "camelCase")]
+    Progress {
+        done: usize,
+        total: usize,
+        current_path: String,
+    },
+    #[serde(rename_all = "camelCase")]
+    Finished { cancelled: bool },
+}
+
+pub struct MetadataBatches {
+    next_id: u32,
+    cancel_flags: HashMap<u32, Arc<AtomicBool>>,
+}
+
+impl MetadataBatches {
+    pub fn new() -> Self {
+        Self {
+            next_id: 0,
+            cancel_flags: HashMap::new(),
+        }
+    }
+
+    fn start(&mut self) -> (u32, Arc<AtomicBool>) {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        let cancelled = Arc::new(AtomicBool::new(false));
+        self.cancel_flags.insert(id, cancelled.clone());
+        (id, cancelled)
+    }
+
+    fn cancel(&mut self, id: u32) {
+        if let Some(cancelled) = self.cancel_flags.get(&id) {
+            cancelled.store(true, Ordering::Relaxed);
+        }
+    }
+
+    fn finish(&mut self, id: u32) {
+        self.cancel_flags.remove(&id);
+    }
+}
+
+fn run_batch(
+    app: &AppHandle,
+    file_paths: &[String],
+    cancelled: &AtomicBool,
+    on_event: &Channel<MetadataBatchEvent>,
+) {
+    let next_index = AtomicUsize::new(0);
+    let done = AtomicUsize::new(0);
+    let total = file_paths.len();
+    let workers = thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+        .clamp(1, MAX_WORKERS)
+        .min(total.max(1));
+
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| loop {
+                let permit = WorkerPermit::acquire();
+                if cancelled.load(Ordering::Relaxed) {
+                    break;
+                }
+                let index = next_index.fetch_add(1, Ordering::Relaxed);
+                let Some(file_path) = file_paths.get(index) else {
+                    break;
+                };
+                let event = match get_metadata(app.clone(), file_path.clone()) {
+                    Ok(metadata) => MetadataBatchEvent::Item {
+                        file_path: file_path.clone(),
+                        metadata: Some(Box::new(metadata)),
+                        error: None,
+                    },
+                    Err(error) => MetadataBatchEvent::Item {
+                        file_path: file_path.clone(),
+                        metadata: None,
+                        error: Some(error),
+                    },
+                };
+                drop(permit);
+                let _ = on_event.send(event);
+                let _ = on_event.send(MetadataBatchEvent::Progress {
+                    done: done.fetch_add(1, Ordering::Relaxed) + 1,
+                    total,
+                    current_path: file_path.clone(),
+                });
+            });
+        }
+    });
+}
+
+type BatchesState<'a> = State<'a, Mutex<MetadataBatches>>;
+
+/// Reads metadata for many files on a background worker pool, streaming each result
+/// through `on_event`. Returns an ID that can be passed to `cancel_metadata_batch`.
+#[tauri::command]
+pub fn get_metadata_batch(
+    app: AppHandle,
+    state: BatchesState,
+    file_paths: Vec<String>,
+    on_event: Channel<MetadataBatchEvent>,
+) -> u32 {
+    let (id, cancelled) = state.lock().unwrap().start();
+    thread::spawn(move || {
+        run_batch(&app, &file_paths, &cancelled, &on_event);
+        app.state::<Mutex<MetadataBatches>>()
+            .lock()
+            .unwrap()
+            .finish(id);
+        let _ = on_event.send(MetadataBatchEvent::Finished {
+            cancelled: cancelled.load(Ordering::Relaxed),
+        });
+    });
+    id
+}
+
+#[tauri::command]
+pub fn cancel_metadata_batch(state: BatchesState, batch_id: u32) {
+    state.lock().unwrap().cancel(batch_id);
+}
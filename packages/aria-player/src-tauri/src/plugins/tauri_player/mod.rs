@@ -0,0 +1,243 @@
+pub mod artwork;
+pub mod audio_output;
+pub mod audio_properties;
+pub mod crossfade;
+pub mod cue_sheet;
+pub mod decoder;
+pub mod dsp;
+pub mod extended_tags;
+pub mod loudness;
+pub mod lyrics;
+pub mod metadata_batch;
+pub mod normalization;
+pub mod playback;
+pub mod playlist;
+pub mod scan_index;
+pub mod scanner;
+pub mod tag_editor;
+pub mod tag_journal;
+pub mod track_metadata;
+pub mod watcher;
+
+use artwork::{cache_embedded_artwork, cache_folder_artwork, ArtworkInfo};
+use audio_properties::set_audio_properties;
+use cue_sheet::{
+    get_cue_track_metadata, get_cue_track_playback_metadata, get_embedded_cue_track_uris,
+    get_file_path, parse_virtual_track_uri,
+};
+use extended_tags::set_extended_tags;
+use lofty::config::ParseOptions;
+use lofty::prelude::{Accessor, AudioFile, ItemKey, TaggedFileExt};
+use lofty::probe::Probe;
+use loudness::set_loudness_metadata;
+use scanner::{walk_audio_files, AudioFilesScan, ScanOptions};
+use sha2::{Digest, Sha256};
+use std::fs::metadata;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+use tauri::{AppHandle, Manager};
+use tauri_plugin_opener::OpenerExt;
+use track_metadata::{parse_date, parse_year, TrackMetadata, METADATA_SCHEMA_VERSION};
+
+pub struct FileStats {
+    pub date_modified: u64,
+    pub file_size: u64,
+}
+
+pub fn get_file_stats(path: &Path) -> Result<FileStats, String> {
+    let file_metadata = metadata(path).map_err(|e| e.to_string())?;
+    let date_modified = file_metadata
+        .modified()
+        .map_err(|e| e.to_string())?
+        .duration_since(UNIX_EPOCH)
+        .map_err(|e| e.to_string())?
+        .as_millis() as u64;
+    Ok(FileStats {
+        date_modified,
+        file_size: file_metadata.len(),
+    })
+}
+
+/// Decodes text files written by other programs, such as cue sheets and playlists. They're
+/// often saved in a legacy encoding, so text that isn't valid UTF-8 is read as Latin-1,
+/// which at least keeps ASCII paths and timestamps intact.
+pub fn decode_text(contents: &[u8]) -> String {
+    let contents = contents.strip_prefix(b"\xef\xbb\xbf").unwrap_or(contents);
+    match std::str::from_utf8(contents) {
+        Ok(text) => text.to_string(),
+        Err(_) => contents.iter().map(|byte| *byte as char).collect(),
+    }
+}
+
+pub fn sha256_hex(data: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(data);
+    hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<String>()
+}
+
+#[cfg(target_os = "windows")]
+fn is_placeholder_file(path: &Path) -> bool {
+    use std::os::windows::fs::MetadataExt;
+    const FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS: u32 = 0x00400000;
+    if let Ok(meta) = metadata(path) {
+        let attrs = meta.file_attributes();
+        (attrs & FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS) != 0
+    } else {
+        false
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn is_placeholder_file(path: &Path) -> bool {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    const UF_DATALESS: u32 = 0x40000000;
+    let c_path = match CString::new(path.as_os_str().as_bytes()) {
+        Ok(p) => p,
+        Err(_) => return false,
+    };
+    let mut stat_buf: libc::stat = unsafe { std::mem::zeroed() };
+    if unsafe { libc::stat(c_path.as_ptr(), &mut stat_buf) } == 0 {
+        (stat_buf.st_flags as u32 & UF_DATALESS) != 0
+    } else {
+        false
+    }
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn is_placeholder_file(_path: &Path) -> bool {
+    false
+}
+
+#[tauri::command]
+pub fn get_audio_files_from_directory(app: AppHandle, directory_path: &Path) -> AudioFilesScan {
+    let asset_scope = app.asset_protocol_scope();
+    let _ = asset_scope.allow_directory(directory_path, true);
+    let options = ScanOptions::from_config(&app);
+    let mut files = Vec::new();
+    let errors = walk_audio_files(directory_path, &options, &mut |path| {
+        files.push(path.display().to_string())
+    });
+    AudioFilesScan { files, errors }
+}
+
+#[tauri::command]
+pub fn get_metadata(app: AppHandle, file_path: String) -> Result<TrackMetadata, String> {
+    if let Some((parent_file_path, number)) = parse_virtual_track_uri(&file_path) {
+        return get_cue_track_metadata(app, parent_file_path, number);
+    }
+    let path = Path::new(&file_path);
+    if is_placeholder_file(path) {
+        return Err("placeholder_file".to_string());
+    }
+    let file_stats = get_file_stats(path)?;
+
+    let tagged_file = match Probe::open(path) {
+        Ok(file) => file.read().map_err(|e| e.to_string())?,
+        Err(_) => return Err("Failed to probe the file".to_string()),
+    };
+
+    let properties = tagged_file.properties();
+    let mut metadata = TrackMetadata {
+        schema_version: METADATA_SCHEMA_VERSION,
+        duration: properties.duration().as_millis() as u64,
+        date_modified: file_stats.date_modified,
+        file_size: file_stats.file_size,
+        sample_rate: properties.sample_rate(),
+        bit_rate: properties.audio_bitrate().map(|bit_rate| bit_rate * 1000),
+        ..Default::default()
+    };
+    set_audio_properties(&mut metadata, path, &tagged_file);
+    if ScanOptions::from_config(&app).split_cue_sheets {
+        metadata.cue_track_uris = get_embedded_cue_track_uris(path);
+    }
+
+    let tag = match tagged_file.primary_tag() {
+        Some(primary_tag) => primary_tag,
+        None => {
+            set_loudness_metadata(&mut metadata, path, tagged_file.file_type(), None);
+            set_artwork(&mut metadata, cache_folder_artwork(&app, path)?);
+            return Ok(metadata);
+        }
+    };
+
+    metadata.title = tag.title().map(|title| title.to_string());
+    metadata.artist = tag
+        .get_strings(ItemKey::TrackArtist)
+        .map(str::to_string)
+        .collect();
+    metadata.album = tag.album().map(|album| album.to_string());
+    metadata.album_artist = tag.get_string(ItemKey::AlbumArtist).map(str::to_string);
+    metadata.genre = tag
+        .get_strings(ItemKey::Genre)
+        .map(str::to_string)
+        .collect();
+    metadata.composer = tag
+        .get_strings(ItemKey::Composer)
+        .map(str::to_string)
+        .collect();
+    metadata.comments = tag
+        .get_strings(ItemKey::Comment)
+        .map(str::to_string)
+        .collect();
+    metadata.year = tag
+        .get_string(ItemKey::RecordingDate)
+        .or_else(|| tag.get_string(ItemKey::Year))
+        .and_then(parse_year);
+    metadata.date_released = tag.get_string(ItemKey::ReleaseDate).and_then(parse_date);
+    metadata.track = tag.track();
+    metadata.disc = tag.disk();
+    set_extended_tags(&mut metadata, tag);
+    set_loudness_metadata(&mut metadata, path, tagged_file.file_type(), Some(tag));
+    let mut artwork = cache_embedded_artwork(&app, tag)?;
+    if artwork.is_empty() {
+        artwork = cache_folder_artwork(&app, path)?;
+    }
+    set_artwork(&mut metadata, artwork);
+    Ok(metadata)
+}
+
+/// Reads only what the playback engine uses: the duration, the album for crossfading and
+/// the loudness gains. Artwork isn't read or cached, so it can't hold up or fail playback.
+pub fn get_playback_metadata(file_path: &str) -> Result<TrackMetadata, String> {
+    if let Some((parent_file_path, number)) = parse_virtual_track_uri(file_path) {
+        return get_cue_track_playback_metadata(parent_file_path, number);
+    }
+    let path = Path::new(file_path);
+    let options = ParseOptions::new().read_cover_art(false);
+    let tagged_file = Probe::open(path)
+        .map_err(|e| e.to_string())?
+        .options(options)
+        .read()
+        .map_err(|e| e.to_string())?;
+    let mut metadata = TrackMetadata {
+        schema_version: METADATA_SCHEMA_VERSION,
+        duration: tagged_file.properties().duration().as_millis() as u64,
+        ..Default::default()
+    };
+    let tag = tagged_file.primary_tag();
+    if let Some(tag) = tag {
+        metadata.artist = tag
+            .get_strings(ItemKey::TrackArtist)
+            .map(str::to_string)
+            .collect();
+        metadata.album = tag.album().map(|album| album.to_string());
+        metadata.album_artist = tag.get_string(ItemKey::AlbumArtist).map(str::to_string);
+    }
+    set_loudness_metadata(&mut metadata, path, tagged_file.file_type(), tag);
+    Ok(metadata)
+}
+
+fn set_artwork(metadata: &mut TrackMetadata, artwork: Vec<ArtworkInfo>) {
+    metadata.artwork_uri = artwork.first().map(|cover| cover.hash.clone());
+    metadata.artwork = artwork;
+}
+
+#[tauri::command]
+pub fn show_file_in_manager(app: AppHandle, uri: String) {
+    let _ = app.opener().reveal_item_in_dir(get_file_path(&uri));
+}
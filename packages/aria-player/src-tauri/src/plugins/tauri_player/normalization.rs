@@ -0,0 +1,281 @@
+use super::playback::PlaybackEngine;
+use super::track_metadata::TrackMetadata;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::sync::Mutex;
+use tauri::{AppHandle, State, Wry};
+use tauri_plugin_store::StoreExt;
+
+/// ReplayGain 2.0 gains are relative to -18 LUFS.
+const REPLAYGAIN_REFERENCE_LOUDNESS: f64 = -18.0;
+/// Opus R128 gains are relative to -23 LUFS.
+const R128_REFERENCE_LOUDNESS: f64 = -23.0;
+/// The target loudnesses offered in the settings, in LUFS.
+const TARGET_LOUDNESS_RANGE: (f64, f64) = (-30.0, -5.0);
+/// The pre-gains offered in the settings, in dB.
+const PRE_GAIN_RANGE: (f64, f64) = (-15.0, 15.0);
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum NormalizationMode {
+    Off,
+    /// Makes every track equally loud.
+    Track,
+    /// Makes every album equally loud, keeping the differences between its tracks.
+    Album,
+}
+
+/// Settings for volume normalization, saved in the `normalization` setting.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase", default)]
+pub struct NormalizationSettings {
+    pub mode: NormalizationMode,
+    /// The loudness to normalize to, in LUFS.
+    pub target_loudness: f64,
+    /// Gain for tracks without ReplayGain or R128 tags, in dB.
+    pub pre_gain: f64,
+    /// Keeps the gain low enough that the track's peak doesn't clip.
+    pub prevent_clipping: bool,
+}
+
+impl Default for NormalizationSettings {
+    fn default() -> Self {
+        Self {
+            mode: NormalizationMode::Off,
+            target_loudness: REPLAYGAIN_REFERENCE_LOUDNESS,
+            pre_gain: 0.0,
+            prevent_clipping: true,
+        }
+    }
+}
+
+impl NormalizationSettings {
+    pub fn from_config(app: &AppHandle<Wry>) -> Self {
+        app.store(".app-config")
+            .ok()
+            .and_then(|store| store.get("normalization"))
+            .and_then(|settings| serde_json::from_value::<Self>(settings).ok())
+            .unwrap_or_default()
+            .clamped()
+    }
+
+    /// Keeps the levels within the ranges offered in the settings, since anything else
+    /// could make playback painfully loud.
+    fn clamped(self) -> Self {
+        let default = Self::default();
+        let clamp = |value: f64, (min, max): (f64, f64), default: f64| {
+            if value.is_finite() {
+                value.clamp(min, max)
+            } else {
+                default
+            }
+        };
+        Self {
+            target_loudness: clamp(
+                self.target_loudness,
+                TARGET_LOUDNESS_RANGE,
+                default.target_loudness,
+            ),
+            pre_gain: clamp(self.pre_gain, PRE_GAIN_RANGE, default.pre_gain),
+            ..self
+        }
+    }
+
+    /// The linear gain to play a track at. Album mode falls back to the track's gain
+    /// when the album's is missing, and track mode falls back the other way.
+    pub fn track_gain(&self, metadata: &TrackMetadata) -> f32 {
+        let (gain, peak) = match self.mode {
+            NormalizationMode::Off => return 1.0,
+            NormalizationMode::Track => (
+                self.track_gain_db(metadata)
+                    .or(self.album_gain_db(metadata)),
+                metadata
+                    .replay_gain_track_peak
+                    .or(metadata.replay_gain_album_peak),
+            ),
+            NormalizationMode::Album => (
+                self.album_gain_db(metadata)
+                    .or(self.track_gain_db(metadata)),
+                metadata
+                    .replay_gain_album_peak
+                    .or(metadata.replay_gain_track_peak),
+            ),
+        };
+        let mut gain = 10f64.powf(gain.unwrap_or(self.pre_gain) / 20.0);
+        if let Some(peak) = peak.filter(|&peak| self.prevent_clipping && peak > 0.0) {
+            gain = gain.min(1.0 / peak);
+        }
+        gain as f32
+    }
+
+    fn track_gain_db(&self, metadata: &TrackMetadata) -> Option<f64> {
+        self.adjust_gain(metadata.replay_gain_track_gain, metadata.r128_track_gain)
+    }
+
+    fn album_gain_db(&self, metadata: &TrackMetadata) -> Option<f64> {
+        self.adjust_gain(metadata.replay_gain_album_gain, metadata.r128_album_gain)
+    }
+
+    /// Moves a ReplayGain or R128 gain from its reference level to the target.
+    fn adjust_gain(&self, replay_gain: Option<f64>, r128_gain: Option<f64>) -> Option<f64> {
+        replay_gain
+            .map(|gain| gain + self.target_loudness - REPLAYGAIN_REFERENCE_LOUDNESS)
+            .or_else(|| r128_gain.map(|gain| gain + self.target_loudness - R128_REFERENCE_LOUDNESS))
+    }
+}
+
+type EngineState<'a> = State<'a, Mutex<PlaybackEngine>>;
+
+#[tauri::command]
+pub fn get_normalization_settings(app: AppHandle) -> NormalizationSettings {
+    NormalizationSettings::from_config(&app)
+}
+
+/// Saves the normalization settings and applies them to what's playing.
+#[tauri::command]
+pub fn set_normalization_settings(
+    app: AppHandle,
+    state: EngineState,
+    settings: NormalizationSettings,
+) -> Result<(), String> {
+    let settings = settings.clamped();
+    let store = app.store(".app-config").map_err(|e| e.to_string())?;
+    store.set("normalization", json!(settings));
+    store.save().map_err(|e| e.to_string())?;
+    state
+        .lock()
+        .unwrap()
+        .set_normalization_settings(&app, settings);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(mode: NormalizationMode) -> NormalizationSettings {
+        NormalizationSettings {
+            mode,
+            ..Default::default()
+        }
+    }
+
+    fn assert_gain(gain: f32, db: f64) {
+        let expected = 10f64.powf(db / 20.0) as f32;
+        assert!(
+            (gain - expected).abs() < 1e-4,
+            "expected {} dB, got {} dB",
+            db,
+            20.0 * gain.log10()
+        );
+    }
+
+    #[test]
+    fn off_leaves_gain_unchanged() {
+        let metadata = TrackMetadata {
+            replay_gain_track_gain: Some(-6.0),
+            ..Default::default()
+        };
+        assert_eq!(settings(NormalizationMode::Off).track_gain(&metadata), 1.0);
+    }
+
+    #[test]
+    fn converts_gains_to_the_target_loudness() {
+        let mut settings = settings(NormalizationMode::Track);
+        let replay_gain = TrackMetadata {
+            replay_gain_track_gain: Some(-6.0),
+            ..Default::default()
+        };
+        let r128 = TrackMetadata {
+            r128_track_gain: Some(-6.0),
+            ..Default::default()
+        };
+        assert_gain(settings.track_gain(&replay_gain), -6.0);
+        // R128 gains are 5 dB quieter than ReplayGain's reference
+        assert_gain(settings.track_gain(&r128), -1.0);
+        settings.target_loudness = -14.0;
+        assert_gain(settings.track_gain(&replay_gain), -2.0);
+        assert_gain(settings.track_gain(&r128), 3.0);
+    }
+
+    #[test]
+    fn prefers_replaygain_over_r128() {
+        let metadata = TrackMetadata {
+            replay_gain_track_gain: Some(-6.0),
+            r128_track_gain: Some(-10.0),
+            ..Default::default()
+        };
+        assert_gain(
+            settings(NormalizationMode::Track).track_gain(&metadata),
+            -6.0,
+        );
+    }
+
+    #[test]
+    fn falls_back_between_track_and_album_gains() {
+        let track_only = TrackMetadata {
+            replay_gain_track_gain: Some(-4.0),
+            ..Default::default()
+        };
+        let album_only = TrackMetadata {
+            replay_gain_album_gain: Some(-8.0),
+            ..Default::default()
+        };
+        let both = TrackMetadata {
+            replay_gain_track_gain: Some(-4.0),
+            replay_gain_album_gain: Some(-8.0),
+            ..Default::default()
+        };
+        let track = settings(NormalizationMode::Track);
+        let album = settings(NormalizationMode::Album);
+        assert_gain(track.track_gain(&both), -4.0);
+        assert_gain(album.track_gain(&both), -8.0);
+        assert_gain(track.track_gain(&album_only), -8.0);
+        assert_gain(album.track_gain(&track_only), -4.0);
+    }
+
+    #[test]
+    fn uses_pre_gain_without_tags() {
+        let settings = NormalizationSettings {
+            pre_gain: -3.0,
+            ..settings(NormalizationMode::Album)
+        };
+        assert_gain(settings.track_gain(&TrackMetadata::default()), -3.0);
+    }
+
+    #[test]
+    fn keeps_peaks_from_clipping() {
+        let metadata = TrackMetadata {
+            replay_gain_track_gain: Some(6.0),
+            replay_gain_track_peak: Some(0.8),
+            replay_gain_album_peak: Some(0.625),
+            ..Default::default()
+        };
+        let mut settings = settings(NormalizationMode::Track);
+        assert!((settings.track_gain(&metadata) - 1.25).abs() < 1e-6);
+        settings.mode = NormalizationMode::Album;
+        assert!((settings.track_gain(&metadata) - 1.6).abs() < 1e-6);
+        settings.prevent_clipping = false;
+        assert_gain(settings.track_gain(&metadata), 6.0);
+    }
+
+    #[test]
+    fn clamps_levels_to_the_settings_ranges() {
+        let settings = NormalizationSettings {
+            target_loudness: -60.0,
+            pre_gain: 40.0,
+            ..Default::default()
+        }
+        .clamped();
+        assert_eq!(settings.target_loudness, -30.0);
+        assert_eq!(settings.pre_gain, 15.0);
+        let settings = NormalizationSettings {
+            target_loudness: f64::NAN,
+            pre_gain: f64::INFINITY,
+            ..Default::default()
+        }
+        .clamped();
+        assert_eq!(settings.target_loudness, REPLAYGAIN_REFERENCE_LOUDNESS);
+        assert_eq!(settings.pre_gain, 0.0);
+    }
+}
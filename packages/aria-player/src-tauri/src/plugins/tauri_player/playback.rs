@@ -0,0 +1,1029 @@
+use super::audio_output::{
+    convert_channels, open_output, AudioOutput, LinearResampler, OutputKind,
+};
+use super::audio_properties::read_itunes_gapless_info;
+use super::crossfade::{Crossfade, FadeSettings, GainRamp};
+use super::decoder::AudioDecoder;
+use super::dsp::{DspChain, DspSettings};
+use super::get_playback_metadata;
+use super::normalization::NormalizationSettings;
+use super::track_metadata::TrackMetadata;
+use lofty::file::FileType;
+use serde::Serialize;
+use std::path::Path;
+use std::sync::mpsc::{self, TryRecvError};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, State};
+
+/// How often the position is reported while playing, in milliseconds.
+const STATUS_INTERVAL: u64 = 250;
+/// How much of the next track is decoded ahead of time, in milliseconds.
+const PREFETCH_LENGTH: u64 = 500;
+
+enum PlaybackCommand {
+    Load {
+        uri: String,
+        position: u64,
+        autoplay: bool,
+    },
+    SetNext(Option<String>),
+    /// Sent by the thread that opens the next track once it's ready.
+    NextOpened {
+        uri: String,
+        result: Result<Box<TrackSource>, String>,
+    },
+    Play,
+    Pause,
+    Seek(u64),
+    SetVolume(f32),
+    SetDsp(DspSettings),
+    SetNormalization(NormalizationSettings),
+    Stop,
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum PlaybackState {
+    Stopped,
+    Loading,
+    Playing,
+    Paused,
+    /// The track played to the end, and there was no next track to continue with.
+    Ended,
+}
+
+/// Sent to the frontend as `playback_status` whenever the state changes, and
+/// periodically while playing.
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PlaybackStatus {
+    pub state: PlaybackState,
+    pub uri: Option<String>,
+    /// Position in the track in milliseconds.
+    pub position: u64,
+    /// Duration in milliseconds, from the track's metadata.
+    pub duration: Option<u64>,
+}
+
+/// Sent to the frontend as `playback_track_changed` when playback continues into the
+/// next track without a gap, once the next track can be heard.
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TrackChange {
+    pub previous_uri: String,
+    pub uri: String,
+}
+
+/// Sent to the frontend as `playback_error` when a track can't be loaded or played.
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PlaybackError {
+    /// The track that failed, or `None` when the audio output did.
+    pub uri: Option<String>,
+    pub message: String,
+}
+
+/// Plays audio natively instead of through the webview. Commands are handled in order
+/// on a playback thread, which owns the decoder and the audio output.
+pub struct PlaybackEngine {
+    sender: Option<mpsc::Sender<PlaybackCommand>>,
+}
+
+impl PlaybackEngine {
+    pub fn new() -> Self {
+        Self { sender: None }
+    }
+
+    fn ensure_worker_running(&mut self, app: &AppHandle) {
+        if self.sender.is_some() {
+            return;
+        }
+        let (tx, rx) = mpsc::channel::<PlaybackCommand>();
+        self.sender = Some(tx.clone());
+        let app = app.clone();
+        thread::spawn(move || worker_loop(app, tx, rx));
+    }
+
+    fn send(&mut self, app: &AppHandle, cmd: PlaybackCommand) {
+        self.ensure_worker_running(app);
+        if let Some(ref tx) = self.sender {
+            let _ = tx.send(cmd);
+        }
+    }
+
+    /// Applies new DSP settings to what's playing.
+    pub fn set_dsp_settings(&mut self, app: &AppHandle, settings: DspSettings) {
+        self.send(app, PlaybackCommand::SetDsp(settings));
+    }
+
+    /// Applies new normalization settings to what's playing.
+    pub fn set_normalization_settings(&mut self, app: &AppHandle, settings: NormalizationSettings) {
+        self.send(app, PlaybackCommand::SetNormalization(settings));
+    }
+}
+
+/// A track decoded from a file, or from its section of the file for tracks from a
+/// cue sheet, and converted to the output's format.
+struct TrackSource {
+    uri: String,
+    metadata: TrackMetadata,
+    decoder: AudioDecoder,
+    resampler: LinearResampler,
+    output_channels: usize,
+    output_sample_rate: u32,
+    /// Where the track starts and ends in the file, in milliseconds.
+    start: u64,
+    end: Option<u64>,
+    /// Frames decoded from the file so far, counted from the start of the file.
+    file_frames: u64,
+    /// Frames produced for the output so far, counted from the start of the track.
+    output_frames: u64,
+    converted: Vec<f32>,
+    /// Samples decoded ahead of time, for the next track.
+    prefetched: Vec<f32>,
+    /// Linear gain from volume normalization.
+    gain: f32,
+}
+
+impl TrackSource {
+    /// Opens a file or virtual track using the same URIs as the scanner, with the
+    /// section to play taken from `get_playback_metadata`.
+    fn open(uri: &str) -> Result<Self, String> {
+        let metadata = get_playback_metadata(uri)?;
+        let file_path = metadata.parent_file_path.as_deref().unwrap_or(uri);
+        let start = metadata.start_offset.unwrap_or(0);
+        let end = metadata
+            .start_offset
+            .map(|start_offset| start_offset + metadata.duration);
+        let mut decoder = AudioDecoder::open(Path::new(file_path))?;
+        // MP3 and Ogg decoders remove encoder delay and padding themselves, but
+        // AAC in MP4 relies on iTunes' tag
+        if FileType::from_path(file_path) == Some(FileType::Mp4) {
+            if let Some((delay, length)) = read_itunes_gapless_info(Path::new(file_path)) {
+                decoder.set_gapless_trim(delay, length);
+            }
+        }
+        if start > 0 {
+            decoder.seek(start)?;
+        }
+        let sample_rate = decoder.sample_rate();
+        let channels = decoder.channels();
+        Ok(Self {
+            uri: uri.to_string(),
+            metadata,
+            file_frames: start * sample_rate as u64 / 1000,
+            resampler: LinearResampler::new(sample_rate, sample_rate, channels),
+            output_channels: channels,
+            output_sample_rate: sample_rate,
+            decoder,
+            start,
+            end,
+            output_frames: 0,
+            converted: Vec::new(),
+            prefetched: Vec::new(),
+            gain: 1.0,
+        })
+    }
+
+    /// Opens the track that comes next and decodes its start, which is done on its own
+    /// thread so the output doesn't run out of audio while the file is read.
+    fn open_next(uri: &str, output_format: Option<(u32, usize)>) -> Result<Self, String> {
+        let mut track = Self::open(uri)?;
+        if let Some((sample_rate, channels)) = output_format {
+            track.set_output_format(sample_rate, channels);
+        }
+        track.prefetch()?;
+        Ok(track)
+    }
+
+    /// Converts the track to the output's format. Anything already decoded is discarded
+    /// when the format changes, so the track has to be seeked afterwards.
+    fn set_output_format(&mut self, sample_rate: u32, channels: usize) -> bool {
+        if sample_rate == self.output_sample_rate && channels == self.output_channels {
+            return false;
+        }
+        self.resampler = LinearResampler::new(self.decoder.sample_rate(), sample_rate, channels);
+        self.output_sample_rate = sample_rate;
+        self.output_channels = channels;
+        true
+    }
+
+    fn seek(&mut self, position: u64) -> Result<(), String> {
+        let position = self
+            .end
+            .map_or(position, |end| position.min(end - self.start));
+        self.decoder.seek(self.start + position)?;
+        self.file_frames = (self.start + position) * self.decoder.sample_rate() as u64 / 1000;
+        self.output_frames = position * self.output_sample_rate as u64 / 1000;
+        self.resampler = LinearResampler::new(
+            self.decoder.sample_rate(),
+            self.output_sample_rate,
+            self.output_channels,
+        );
+        self.prefetched.clear();
+        Ok(())
+    }
+
+    /// Decodes the start of the track ahead of time, so it can follow the current track
+    /// without waiting for the file to be read.
+    fn prefetch(&mut self) -> Result<(), String> {
+        let length = (self.output_sample_rate as u64 * PREFETCH_LENGTH / 1000) as usize
+            * self.output_channels;
+        let mut samples = Vec::new();
+        while self.prefetched.len() < length && self.decode(&mut samples)? {
+            self.prefetched.extend_from_slice(&samples);
+        }
+        Ok(())
+    }
+
+    /// Position in milliseconds, not counting frames that are still queued in the output.
+    fn position(&self, queued_frames: usize) -> u64 {
+        self.output_frames.saturating_sub(queued_frames as u64) * 1000
+            / self.output_sample_rate as u64
+    }
+
+    /// Reads the next chunk of audio into `samples`, returning false at the end of the track.
+    fn read(&mut self, samples: &mut Vec<f32>) -> Result<bool, String> {
+        if !self.prefetched.is_empty() {
+            samples.clear();
+            samples.append(&mut self.prefetched);
+        } else if !self.decode(samples)? {
+            return Ok(false);
+        }
+        self.output_frames += (samples.len() / self.output_channels) as u64;
+        if self.gain != 1.0 {
+            for sample in samples.iter_mut() {
+                *sample *= self.gain;
+            }
+        }
+        Ok(true)
+    }
+
+    fn decode(&mut self, samples: &mut Vec<f32>) -> Result<bool, String> {
+        let channels = self.decoder.channels();
+        let end_frame = self
+            .end
+            .map(|end| end * self.decoder.sample_rate() as u64 / 1000);
+        if end_frame.is_some_and(|end_frame| self.file_frames >= end_frame) {
+            return Ok(false);
+        }
+        let Some(decoded) = self.decoder.next_samples()? else {
+            return Ok(false);
+        };
+        let mut frames = (decoded.len() / channels) as u64;
+        if let Some(end_frame) = end_frame {
+            frames = frames.min(end_frame - self.file_frames);
+        }
+        self.file_frames += frames;
+        convert_channels(
+            &decoded[..frames as usize * channels],
+            channels,
+            self.output_channels,
+            &mut self.converted,
+        );
+        self.resampler.process(&self.converted, samples);
+        Ok(true)
+    }
+}
+
+/// A track that finished decoding while the output still has some of it queued.
+struct EndingTrack {
+    uri: String,
+    duration: u64,
+    /// Where the track was when the next track started, in milliseconds.
+    position: u64,
+}
+
+struct Player {
+    app: AppHandle,
+    /// Used to send the next track back to the playback thread once it's opened.
+    sender: mpsc::Sender<PlaybackCommand>,
+    output_kind: Option<OutputKind>,
+    output: Option<Box<dyn AudioOutput>>,
+    track: Option<TrackSource>,
+    /// The track that will play after the current one, opened ahead of time.
+    next_track: Option<TrackSource>,
+    /// The next track while it's being opened.
+    opening_uri: Option<String>,
+    /// Set after continuing into the next track until it can be heard.
+    ending_track: Option<EndingTrack>,
+    /// Set after continuing into the next track until the frontend loads it.
+    continued_uri: Option<String>,
+    /// The previous track while it's crossfading into the current one.
+    crossfade: Option<(TrackSource, Crossfade)>,
+    fade_settings: FadeSettings,
+    ramp: GainRamp,
+    dsp: DspChain,
+    normalization: NormalizationSettings,
+    state: PlaybackState,
+    volume: f32,
+    last_status: Instant,
+    samples: Vec<f32>,
+    outgoing_samples: Vec<f32>,
+}
+
+impl Player {
+    fn new(app: AppHandle, sender: mpsc::Sender<PlaybackCommand>) -> Self {
+        Self {
+            sender,
+            fade_settings: FadeSettings::from_config(&app),
+            dsp: DspChain::new(DspSettings::from_config(&app)),
+            normalization: NormalizationSettings::from_config(&app),
+            app,
+            output_kind: None,
+            output: None,
+            track: None,
+            next_track: None,
+            opening_uri: None,
+            ending_track: None,
+            continued_uri: None,
+            crossfade: None,
+            ramp: GainRamp::new(),
+            state: PlaybackState::Stopped,
+            volume: 1.0,
+            last_status: Instant::now(),
+            samples: Vec::new(),
+            outgoing_samples: Vec::new(),
+        }
+    }
+
+    fn emit_status(&mut self) {
+        let queued_frames = self
+            .output
+            .as_ref()
+            .map_or(0, |output| output.queued_frames());
+        if let (Some(ending_track), Some(track)) = (&self.ending_track, &self.track) {
+            // The rest of the previous track is queued ahead of the current one
+            let remaining_frames = (queued_frames as u64).saturating_sub(track.output_frames);
+            let remaining = remaining_frames * 1000 / track.output_sample_rate as u64;
+            let status = PlaybackStatus {
+                state: self.state,
+                uri: Some(ending_track.uri.clone()),
+                position: ending_track.position.saturating_sub(remaining),
+                duration: Some(ending_track.duration),
+            };
+            let _ = self.app.emit("playback_status", status);
+            self.last_status = Instant::now();
+            return;
+        }
+        let status = PlaybackStatus {
+            state: self.state,
+            uri: self.track.as_ref().map(|track| track.uri.clone()),
+            position: self
+                .track
+                .as_ref()
+                .map_or(0, |track| track.position(queued_frames)),
+            duration: self.track.as_ref().map(|track| track.metadata.duration),
+        };
+        let _ = self.app.emit("playback_status", status);
+        self.last_status = Instant::now();
+    }
+
+    fn set_state(&mut self, state: PlaybackState) {
+        self.state = state;
+        self.emit_status();
+    }
+
+    fn current_uri(&self) -> Option<String> {
+        self.track.as_ref().map(|track| track.uri.clone())
+    }
+
+    fn emit_error(&self, uri: Option<String>, message: String) {
+        let _ = self
+            .app
+            .emit("playback_error", PlaybackError { uri, message });
+    }
+
+    /// Opens the output configured in `.app-config`, reopening it when the setting
+    /// changes or when an audio device could play the track without resampling.
+    fn prepare_output(&mut self, sample_rate: u32, channels: usize) -> Result<(), String> {
+        let kind = OutputKind::from_config(&self.app);
+        let reopen = match &self.output {
+            None => true,
+            Some(output) => {
+                self.output_kind.as_ref() != Some(&kind)
+                    || (kind == OutputKind::Device && output.sample_rate() != sample_rate)
+            }
+        };
+        if reopen {
+            // Close the old output first, since devices can't always be opened twice
+            self.output = None;
+            self.output = Some(open_output(&kind, sample_rate, channels)?);
+            self.output_kind = Some(kind);
+        }
+        Ok(())
+    }
+
+    /// The length of fades when pausing, resuming and seeking, in output frames.
+    fn fade_frames(&self) -> u64 {
+        self.output.as_ref().map_or(0, |output| {
+            self.fade_settings.fade_duration * output.sample_rate() as u64 / 1000
+        })
+    }
+
+    /// Opens a track with its gain from volume normalization.
+    fn open_track(&self, uri: &str) -> Result<TrackSource, String> {
+        let mut track = TrackSource::open(uri)?;
+        track.gain = self.normalization.track_gain(&track.metadata);
+        Ok(track)
+    }
+
+    fn load(&mut self, uri: String, position: u64, autoplay: bool) -> Result<(), String> {
+        // The frontend loads the track playback continued into once it moves on in the
+        // queue, which shouldn't restart it
+        let continued_uri = self.continued_uri.take();
+        if continued_uri.as_ref() == Some(&uri)
+            && position == 0
+            && autoplay
+            && self.track.as_ref().is_some_and(|track| track.uri == uri)
+        {
+            return self.play();
+        }
+        // The track is being replaced either way
+        let _ = self.fade_out(false);
+        if let Some(output) = self.output.as_mut() {
+            output.flush();
+        }
+        self.track = None;
+        self.ending_track = None;
+        self.crossfade = None;
+        if self.opening_uri.as_ref() == Some(&uri) {
+            self.opening_uri = None;
+        }
+        self.set_state(PlaybackState::Loading);
+        let mut track = match self.next_track.take() {
+            Some(next_track) if next_track.uri == uri => next_track,
+            _ => self.open_track(&uri)?,
+        };
+        self.prepare_output(track.decoder.sample_rate(), track.decoder.channels())?;
+        let output = self.output.as_mut().unwrap();
+        let format_changed = track.set_output_format(output.sample_rate(), output.channels());
+        if position > 0 || format_changed {
+            track.seek(position)?;
+        }
+        output.set_paused(!autoplay);
+        self.track = Some(track);
+        // Tracks start from silence, but not when starting part way through
+        self.ramp.set(if position > 0 { 0.0 } else { 1.0 });
+        self.ramp.start(1.0, self.fade_frames());
+        self.set_state(if autoplay {
+            PlaybackState::Playing
+        } else {
+            PlaybackState::Paused
+        });
+        Ok(())
+    }
+
+    fn handle_command(&mut self, cmd: PlaybackCommand) {
+        self.fade_settings = FadeSettings::from_config(&self.app);
+        // Loading clears the current track before opening the new one, so errors are
+        // reported for the track being loaded
+        let loading_uri = match &cmd {
+            PlaybackCommand::Load { uri, .. } => Some(uri.clone()),
+            _ => None,
+        };
+        let result = match cmd {
+            PlaybackCommand::Load {
+                uri,
+                position,
+                autoplay,
+            } => self.load(uri, position, autoplay),
+            PlaybackCommand::SetNext(uri) => {
+                self.set_next_track(uri);
+                Ok(())
+            }
+            PlaybackCommand::NextOpened { uri, result } => {
+                self.finish_opening_next_track(uri, result);
+                Ok(())
+            }
+            PlaybackCommand::Play => self.play(),
+            PlaybackCommand::Pause => self.pause(),
+            PlaybackCommand::Seek(position) => self.seek(position),
+            PlaybackCommand::SetVolume(volume) => {
+                self.volume = volume.clamp(0.0, 1.0);
+                Ok(())
+            }
+            PlaybackCommand::SetDsp(settings) => {
+                self.dsp.set_settings(settings);
+                Ok(())
+            }
+            PlaybackCommand::SetNormalization(settings) => {
+                self.normalization = settings;
+                let outgoing = self.crossfade.as_mut().map(|(outgoing, _)| outgoing);
+                for track in [self.track.as_mut(), self.next_track.as_mut(), outgoing]
+                    .into_iter()
+                    .flatten()
+                {
+                    track.gain = self.normalization.track_gain(&track.metadata);
+                }
+                Ok(())
+            }
+            PlaybackCommand::Stop => {
+                let _ = self.fade_out(false);
+                self.track = None;
+                self.next_track = None;
+                self.opening_uri = None;
+                self.ending_track = None;
+                self.crossfade = None;
+                // Release the device so other apps can use it
+                self.output = None;
+                self.set_state(PlaybackState::Stopped);
+                Ok(())
+            }
+        };
+        if let Err(error) = result {
+            self.emit_error(loading_uri.or_else(|| self.current_uri()), error);
+            self.track = None;
+            self.ending_track = None;
+            self.crossfade = None;
+            self.set_state(PlaybackState::Stopped);
+        }
+    }
+
+    /// Starts opening the next track on another thread, which sends it back as a
+    /// `NextOpened` command.
+    fn set_next_track(&mut self, uri: Option<String>) {
+        let Some(uri) = uri else {
+            self.next_track = None;
+            self.opening_uri = None;
+            return;
+        };
+        if self.opening_uri.as_ref() == Some(&uri)
+            || self
+                .next_track
+                .as_ref()
+                .is_some_and(|next_track| next_track.uri == uri)
+        {
+            return;
+        }
+        self.next_track = None;
+        self.opening_uri = Some(uri.clone());
+        let sender = self.sender.clone();
+        let output_format = self
+            .output
+            .as_ref()
+            .map(|output| (output.sample_rate(), output.channels()));
+        thread::spawn(move || {
+            let result = TrackSource::open_next(&uri, output_format).map(Box::new);
+            let _ = sender.send(PlaybackCommand::NextOpened { uri, result });
+        });
+    }
+
+    /// Keeps the next track once it's opened, unless it was replaced in the meantime.
+    fn finish_opening_next_track(&mut self, uri: String, result: Result<Box<TrackSource>, String>) {
+        if self.opening_uri.as_ref() != Some(&uri) {
+            return;
+        }
+        self.opening_uri = None;
+        match result {
+            Ok(mut next_track) => {
+                next_track.gain = self.normalization.track_gain(&next_track.metadata);
+                self.next_track = Some(*next_track);
+            }
+            // The frontend will try again when it gets to the track
+            Err(message) => self.emit_error(Some(uri), message),
+        }
+    }
+
+    /// Continues into the next track without waiting for the output to play what's
+    /// queued, so there's no gap between them. Returns the previous track.
+    fn continue_to_next_track(&mut self) -> Option<TrackSource> {
+        let (Some(mut next_track), Some(output)) = (self.next_track.take(), self.output.as_ref())
+        else {
+            return None;
+        };
+        if next_track.set_output_format(output.sample_rate(), output.channels())
+            && next_track.seek(0).is_err()
+        {
+            return None;
+        }
+        let track = self.track.take()?;
+        self.ending_track = Some(EndingTrack {
+            uri: track.uri.clone(),
+            duration: track.metadata.duration,
+            position: track.position(0),
+        });
+        self.continued_uri = Some(next_track.uri.clone());
+        self.track = Some(next_track);
+        Some(track)
+    }
+
+    /// Starts crossfading into the next track once the current one is close enough to
+    /// its end, unless they're from the same album and that's turned off.
+    fn check_crossfade(&mut self) {
+        if self.crossfade.is_some() || self.ending_track.is_some() {
+            return;
+        }
+        let (Some(track), Some(next_track)) = (self.track.as_ref(), self.next_track.as_ref())
+        else {
+            return;
+        };
+        if !self
+            .fade_settings
+            .should_crossfade(&track.metadata, &next_track.metadata)
+        {
+            return;
+        }
+        let sample_rate = track.output_sample_rate as u64;
+        // Short tracks only overlap for half their length, so they can still be heard
+        let length = self
+            .fade_settings
+            .crossfade_duration
+            .min(track.metadata.duration / 2)
+            .min(next_track.metadata.duration / 2)
+            * sample_rate
+            / 1000;
+        let remaining =
+            (track.metadata.duration * sample_rate / 1000).saturating_sub(track.output_frames);
+        if remaining > length {
+            return;
+        }
+        if let Some(outgoing) = self.continue_to_next_track() {
+            self.crossfade = Some((outgoing, Crossfade::new(remaining)));
+        }
+    }
+
+    /// Tells the frontend once the previous track has finished playing from the output,
+    /// or has started fading out.
+    fn check_track_change(&mut self) {
+        let (Some(track), Some(output)) = (self.track.as_ref(), self.output.as_ref()) else {
+            return;
+        };
+        if self.ending_track.is_none() || track.output_frames < output.queued_frames() as u64 {
+            return;
+        }
+        let ending_track = self.ending_track.take().unwrap();
+        let change = TrackChange {
+            previous_uri: ending_track.uri,
+            uri: track.uri.clone(),
+        };
+        let _ = self.app.emit("playback_track_changed", change);
+        self.emit_status();
+    }
+
+    fn play(&mut self) -> Result<(), String> {
+        if self.track.is_none() {
+            return Ok(());
+        }
+        if self.state == PlaybackState::Ended {
+            self.seek(0)?;
+        }
+        if let Some(output) = self.output.as_mut() {
+            output.set_paused(false);
+        }
+        self.ramp.start(1.0, self.fade_frames());
+        self.set_state(PlaybackState::Playing);
+        Ok(())
+    }
+
+    fn pause(&mut self) -> Result<(), String> {
+        if self.state != PlaybackState::Playing {
+            return Ok(());
+        }
+        self.fade_out(true)?;
+        if let Some(output) = self.output.as_mut() {
+            output.set_paused(true);
+        }
+        self.set_state(PlaybackState::Paused);
+        Ok(())
+    }
+
+    fn seek(&mut self, position: u64) -> Result<(), String> {
+        if self.track.is_none() {
+            return Ok(());
+        }
+        self.fade_out(false)?;
+        self.crossfade = None;
+        let Some(track) = self.track.as_mut() else {
+            return Ok(());
+        };
+        track.seek(position)?;
+        if let Some(output) = self.output.as_mut() {
+            output.flush();
+        }
+        if self.state == PlaybackState::Playing {
+            self.ramp.start(1.0, self.fade_frames());
+        }
+        if self.state == PlaybackState::Ended {
+            self.state = PlaybackState::Paused;
+        }
+        self.emit_status();
+        Ok(())
+    }
+
+    /// Fades out from what's being heard and waits for it to finish playing, so pausing,
+    /// seeking and switching tracks don't click. With `restore`, the track is moved back
+    /// to where the fade started, to carry on from there.
+    fn fade_out(&mut self, restore: bool) -> Result<(), String> {
+        let frames = self.fade_frames();
+        if self.state != PlaybackState::Playing || frames == 0 {
+            return Ok(());
+        }
+        let (Some(track), Some(output)) = (self.track.as_mut(), self.output.as_mut()) else {
+            return Ok(());
+        };
+        let channels = track.output_channels;
+        // What's queued is replaced by the fade, unless the end of the previous track is
+        // queued ahead of the current one, which is left to play first
+        let mut position = None;
+        if self.crossfade.is_none() && self.ending_track.is_none() {
+            let start = track.position(output.queued_frames());
+            output.flush();
+            track.seek(start)?;
+            position = Some(start);
+        }
+        self.ramp.start(0.0, frames);
+        while !self.ramp.is_done() {
+            let remaining = self.ramp.remaining_frames() as usize;
+            if !self.render_chunk()? {
+                break;
+            }
+            self.samples.truncate(remaining * channels);
+            if let Some(output) = self.output.as_mut() {
+                output.write(&self.samples)?;
+            }
+        }
+        self.ramp.set(0.0);
+        let deadline = Instant::now() + Duration::from_secs(1);
+        while self
+            .output
+            .as_ref()
+            .is_some_and(|output| output.queued_frames() > 0)
+            && Instant::now() < deadline
+        {
+            thread::sleep(Duration::from_millis(5));
+        }
+        self.check_track_change();
+        if let (true, Some(position), Some(track)) = (restore, position, self.track.as_mut()) {
+            track.seek(position)?;
+        }
+        Ok(())
+    }
+
+    /// Reads the next chunk of audio into `samples`, mixing in the previous track while
+    /// crossfading and applying the DSP chain, fades and the volume. Returns false at the end of the track.
+    fn render_chunk(&mut self) -> Result<bool, String> {
+        let Some(track) = self.track.as_mut() else {
+            return Ok(false);
+        };
+        if !track.read(&mut self.samples)? {
+            return Ok(false);
+        }
+        let channels = track.output_channels;
+        let sample_rate = track.output_sample_rate;
+        if let Some((outgoing, crossfade)) = self.crossfade.as_mut() {
+            // The previous track ending early, or failing, just cuts the crossfade short
+            while crossfade.needed_samples(self.samples.len()) > 0
+                && matches!(outgoing.read(&mut self.outgoing_samples), Ok(true))
+            {
+                crossfade.push(&self.outgoing_samples);
+            }
+            crossfade.mix(&mut self.samples, channels);
+            if crossfade.is_done() {
+                self.crossfade = None;
+            }
+        }
+        self.dsp.process(&mut self.samples, sample_rate, channels);
+        self.ramp.apply(&mut self.samples, channels);
+        if self.volume < 1.0 {
+            for sample in self.samples.iter_mut() {
+                *sample *= self.volume;
+            }
+        }
+        Ok(true)
+    }
+
+    /// Decodes and queues the next chunk of audio, which blocks while the output's
+    /// buffer is full.
+    fn play_next_chunk(&mut self) {
+        if self.track.is_none() || self.output.is_none() {
+            self.set_state(PlaybackState::Stopped);
+            return;
+        }
+        self.check_crossfade();
+        match self.render_chunk() {
+            Ok(true) => {
+                let output = self.output.as_mut().unwrap();
+                if let Err(error) = output.write(&self.samples) {
+                    self.emit_error(None, error);
+                    self.output = None;
+                    self.set_state(PlaybackState::Paused);
+                    return;
+                }
+            }
+            Ok(false) => {
+                self.crossfade = None;
+                if self.continue_to_next_track().is_some() {
+                    return;
+                }
+                let Some(output) = self.output.as_ref() else {
+                    return;
+                };
+                // Wait for the output to play what's queued before reporting the end
+                if output.queued_frames() > 0 {
+                    thread::sleep(Duration::from_millis(10));
+                } else {
+                    self.set_state(PlaybackState::Ended);
+                    return;
+                }
+            }
+            Err(error) => {
+                self.emit_error(self.current_uri(), error);
+                self.track = None;
+                self.crossfade = None;
+                self.set_state(PlaybackState::Stopped);
+                return;
+            }
+        }
+        self.check_track_change();
+        if self.last_status.elapsed() >= Duration::from_millis(STATUS_INTERVAL) {
+            self.emit_status();
+        }
+    }
+}
+
+fn worker_loop(
+    app: AppHandle,
+    sender: mpsc::Sender<PlaybackCommand>,
+    rx: mpsc::Receiver<PlaybackCommand>,
+) {
+    let mut player = Player::new(app, sender);
+    loop {
+        // Only wait for commands when there's nothing to play
+        let cmd = if player.state == PlaybackState::Playing {
+            match rx.try_recv() {
+                Ok(cmd) => Some(cmd),
+                Err(TryRecvError::Empty) => None,
+                Err(TryRecvError::Disconnected) => break,
+            }
+        } else {
+            match rx.recv() {
+                Ok(cmd) => Some(cmd),
+                Err(_) => break,
+            }
+        };
+        match cmd {
+            Some(cmd) => player.handle_command(cmd),
+            None => player.play_next_chunk(),
+        }
+    }
+}
+
+type EngineState<'a> = State<'a, Mutex<PlaybackEngine>>;
+
+/// Loads a track by its URI, which can be a file path or a virtual track from a cue
+/// sheet, and starts playing it from `position` in milliseconds unless `autoplay` is false.
+#[tauri::command]
+pub fn load_playback_track(
+    app: AppHandle,
+    state: EngineState,
+    uri: String,
+    position: Option<u64>,
+    autoplay: Option<bool>,
+) {
+    state.lock().unwrap().send(
+        &app,
+        PlaybackCommand::Load {
+            uri,
+            position: position.unwrap_or(0),
+            autoplay: autoplay.unwrap_or(true),
+        },
+    );
+}
+
+/// Opens the track that comes next in the queue, so playback can continue into it
+/// without a gap. `None` clears it, such as at the end of the queue.
+#[tauri::command]
+pub fn set_next_playback_track(app: AppHandle, state: EngineState, uri: Option<String>) {
+    state
+        .lock()
+        .unwrap()
+        .send(&app, PlaybackCommand::SetNext(uri));
+}
+
+#[tauri::command]
+pub fn resume_playback(app: AppHandle, state: EngineState) {
+    state.lock().unwrap().send(&app, PlaybackCommand::Play);
+}
+
+#[tauri::command]
+pub fn pause_playback(app: AppHandle, state: EngineState) {
+    state.lock().unwrap().send(&app, PlaybackCommand::Pause);
+}
+
+/// Moves to `position` in milliseconds.
+#[tauri::command]
+pub fn seek_playback(app: AppHandle, state: EngineState, position: u64) {
+    state
+        .lock()
+        .unwrap()
+        .send(&app, PlaybackCommand::Seek(position));
+}
+
+/// Sets the volume from 0.0 to 1.0.
+#[tauri::command]
+pub fn set_playback_volume(app: AppHandle, state: EngineState, volume: f32) {
+    state
+        .lock()
+        .unwrap()
+        .send(&app, PlaybackCommand::SetVolume(volume));
+}
+
+#[tauri::command]
+pub fn stop_playback(app: AppHandle, state: EngineState) {
+    state.lock().unwrap().send(&app, PlaybackCommand::Stop);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+
+    const SAMPLE_RATE: u32 = 8000;
+
+    /// Writes a three second mono WAV file, where each sample is its frame number, with a
+    /// cue sheet splitting it into one second tracks.
+    fn write_album(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("aria-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let frames = SAMPLE_RATE * 3;
+        let mut wav = Vec::new();
+        wav.extend_from_slice(b"RIFF");
+        wav.extend_from_slice(&(36 + frames * 2).to_le_bytes());
+        wav.extend_from_slice(b"WAVEfmt ");
+        wav.extend_from_slice(&16u32.to_le_bytes());
+        wav.extend_from_slice(&1u16.to_le_bytes());
+        wav.extend_from_slice(&1u16.to_le_bytes());
+        wav.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+        wav.extend_from_slice(&(SAMPLE_RATE * 2).to_le_bytes());
+        wav.extend_from_slice(&2u16.to_le_bytes());
+        wav.extend_from_slice(&16u16.to_le_bytes());
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&(frames * 2).to_le_bytes());
+        for frame in 0..frames {
+            wav.extend_from_slice(&(frame as i16).to_le_bytes());
+        }
+        fs::write(directory.join("album.wav"), wav).unwrap();
+        fs::write(
+            directory.join("album.cue"),
+            "FILE \"album.wav\" WAVE
+  TRACK 01 AUDIO
+    INDEX 01 00:00:00
+  TRACK 02 AUDIO
+    INDEX 01 00:01:00
+  TRACK 03 AUDIO
+    INDEX 01 00:02:00
+",
+        )
+        .unwrap();
+        directory
+    }
+
+    /// Reads the rest of the track, returning the frame numbers of its samples.
+    fn read_frames(track: &mut TrackSource) -> Vec<u32> {
+        let mut frames = Vec::new();
+        let mut samples = Vec::new();
+        while track.read(&mut samples).unwrap() {
+            frames.extend(
+                samples
+                    .iter()
+                    .map(|sample| (sample * 32768.0).round() as u32),
+            );
+        }
+        frames
+    }
+
+    #[test]
+    fn plays_a_cue_sheet_section() {
+        let directory = write_album("cue-section");
+        let uri = format!("{}#track=2", directory.join("album.wav").display());
+        let mut track = TrackSource::open(&uri).unwrap();
+        assert_eq!(track.metadata.duration, 1000);
+        let frames = read_frames(&mut track);
+        assert_eq!(frames.len(), SAMPLE_RATE as usize);
+        assert_eq!(frames.first(), Some(&SAMPLE_RATE));
+        assert_eq!(frames.last(), Some(&(SAMPLE_RATE * 2 - 1)));
+        assert_eq!(track.position(0), 1000);
+
+        track.seek(500).unwrap();
+        let frames = read_frames(&mut track);
+        assert_eq!(frames.first(), Some(&(SAMPLE_RATE * 3 / 2)));
+        assert_eq!(frames.last(), Some(&(SAMPLE_RATE * 2 - 1)));
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn plays_the_last_section_to_the_end_of_the_file() {
+        let directory = write_album("cue-last-section");
+        let uri = format!("{}#track=3", directory.join("album.wav").display());
+        let mut track = TrackSource::open(&uri).unwrap();
+        let frames = read_frames(&mut track);
+        assert_eq!(frames.len(), SAMPLE_RATE as usize);
+        assert_eq!(frames.first(), Some(&(SAMPLE_RATE * 2)));
+        assert_eq!(frames.last(), Some(&(SAMPLE_RATE * 3 - 1)));
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,716 @@
+use super::cue_sheet::{get_file_path, parse_virtual_track_uri};
+use super::decode_text;
+use quick_xml::escape::resolve_predefined_entity;
+use quick_xml::events::{BytesDecl, BytesText, Event};
+use quick_xml::{Reader, Writer};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use url::Url;
+
+/// Schemes of entries that are read as URLs even without `://`.
+const URL_SCHEMES: [&str; 9] = [
+    "file", "http", "https", "ftp", "mms", "rtmp", "rtp", "rtsp", "udp",
+];
+
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum PlaylistFormat {
+    M3u,
+    M3u8,
+    Pls,
+    Xspf,
+}
+
+impl PlaylistFormat {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "m3u" => Some(Self::M3u),
+            "m3u8" => Some(Self::M3u8),
+            "pls" => Some(Self::Pls),
+            "xspf" => Some(Self::Xspf),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct PlaylistEntry {
+    /// An absolute file path, or a URL for streams and other remote entries.
+    pub location: String,
+    #[serde(default)]
+    pub is_url: bool,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    /// Duration in milliseconds.
+    pub duration: Option<u64>,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Playlist {
+    /// The name stored in the playlist, or the file name if it doesn't have one.
+    pub name: String,
+    pub file_path: String,
+    pub format: PlaylistFormat,
+    pub entries: Vec<PlaylistEntry>,
+}
+
+/// Removes `.` and `..` components without touching the filesystem, since playlist
+/// entries can point to drives that aren't connected.
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            _ => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// Finds the path from `base_directory` to `path`, or `None` if they're on different drives.
+fn get_relative_path(base_directory: &Path, path: &Path) -> Option<PathBuf> {
+    let base_components: Vec<Component> = base_directory.components().collect();
+    let components: Vec<Component> = path.components().collect();
+    if base_components.first() != components.first() {
+        return None;
+    }
+    let common_length = base_components
+        .iter()
+        .zip(&components)
+        .take_while(|(base, component)| base == component)
+        .count();
+    let mut relative_path = PathBuf::new();
+    for _ in common_length..base_components.len() {
+        relative_path.push("..");
+    }
+    for component in &components[common_length..] {
+        relative_path.push(component);
+    }
+    Some(relative_path)
+}
+
+/// Checks whether an M3U or PLS entry is a URL. Colons are allowed in file names, as in
+/// `Live: Side A.mp3`, so an entry without `://` is only a URL when its scheme is known.
+fn is_url(location: &str) -> bool {
+    location.contains("://")
+        || location.split_once(':').is_some_and(|(scheme, _)| {
+            URL_SCHEMES
+                .iter()
+                .any(|known_scheme| scheme.eq_ignore_ascii_case(known_scheme))
+        })
+}
+
+/// Resolves an M3U or PLS entry, which can be a URL, or an absolute or relative path.
+fn resolve_location(base_directory: &Path, location: &str) -> Option<(String, bool)> {
+    let location = location.trim();
+    if location.is_empty() {
+        return None;
+    }
+    if let Some(url) = Some(location)
+        .filter(|location| is_url(location))
+        .and_then(|location| Url::parse(location).ok())
+    {
+        // Single letter schemes are Windows drive letters, such as C:\
+        if url.scheme().len() > 1 {
+            if url.scheme() == "file" {
+                let path = url.to_file_path().ok()?;
+                return Some((path.display().to_string(), false));
+            }
+            return Some((location.to_string(), true));
+        }
+    }
+    // Playlists made on Windows use backslashes, which are valid in file names elsewhere
+    let path = if cfg!(windows) {
+        PathBuf::from(location)
+    } else {
+        PathBuf::from(location.replace('\\', "/"))
+    };
+    let path = normalize_path(&base_directory.join(path));
+    Some((path.display().to_string(), false))
+}
+
+/// Resolves an XSPF location, which is a URI that can be relative to the playlist.
+fn resolve_uri(playlist_url: Option<&Url>, location: &str) -> Option<(String, bool)> {
+    let url = match playlist_url {
+        Some(playlist_url) => playlist_url.join(location.trim()).ok()?,
+        None => Url::parse(location.trim()).ok()?,
+    };
+    if url.scheme() == "file" {
+        let path = url.to_file_path().ok()?;
+        Some((path.display().to_string(), false))
+    } else {
+        Some((url.to_string(), true))
+    }
+}
+
+/// Splits the display text of an `#EXTINF` line, usually written as `Artist - Title`.
+fn split_display_title(text: &str) -> (Option<String>, Option<String>) {
+    let text = text.trim();
+    if text.is_empty() {
+        return (None, None);
+    }
+    match text.split_once(" - ") {
+        Some((artist, title)) => (
+            Some(artist.trim().to_string()),
+            Some(title.trim().to_string()),
+        ),
+        None => (None, Some(text.to_string())),
+    }
+}
+
+/// Parses the value of an `#EXTINF` line, such as `245 tvg-id="x",Artist - Title`, into
+/// its duration, artist and title. A duration of -1 means it's unknown.
+fn parse_extinf(value: &str) -> PlaylistEntry {
+    let mut in_quotes = false;
+    let separator = value.char_indices().find(|(_, c)| {
+        if *c == '"' {
+            in_quotes = !in_quotes;
+        }
+        *c == ',' && !in_quotes
+    });
+    let (info, display_title) = match separator {
+        Some((index, _)) => (&value[..index], &value[index + 1..]),
+        None => (value, ""),
+    };
+    let duration = info
+        .split_whitespace()
+        .next()
+        .and_then(|duration| duration.parse::<f64>().ok())
+        .filter(|duration| *duration >= 0.0)
+        .map(|duration| (duration * 1000.0).round() as u64);
+    let (artist, title) = split_display_title(display_title);
+    PlaylistEntry {
+        title,
+        artist,
+        duration,
+        ..Default::default()
+    }
+}
+
+fn parse_m3u(text: &str, base_directory: &Path) -> (Option<String>, Vec<PlaylistEntry>) {
+    let mut name = None;
+    let mut entries = Vec::new();
+    let mut info: Option<PlaylistEntry> = None;
+    let mut album = None;
+    for line in text.lines() {
+        let line = line.trim();
+        if let Some(value) = line.strip_prefix("#EXTINF:") {
+            info = Some(parse_extinf(value));
+        } else if let Some(value) = line.strip_prefix("#PLAYLIST:") {
+            name = Some(value.trim().to_string());
+        } else if let Some(value) = line.strip_prefix("#EXTALB:") {
+            album = Some(value.trim().to_string());
+        } else if line.is_empty() || line.starts_with('#') {
+            continue;
+        } else if let Some((location, is_url)) = resolve_location(base_directory, line) {
+            let info = info.take().unwrap_or_default();
+            entries.push(PlaylistEntry {
+                location,
+                is_url,
+                album: album.take(),
+                ..info
+            });
+        }
+    }
+    (name, entries)
+}
+
+fn parse_pls(text: &str, base_directory: &Path) -> Vec<PlaylistEntry> {
+    // Entries are numbered, and nothing requires the numbers to be in order
+    let mut entries: BTreeMap<u32, PlaylistEntry> = BTreeMap::new();
+    for line in text.lines() {
+        let Some((key, value)) = line.trim().split_once('=') else {
+            continue;
+        };
+        let key = key.trim().to_lowercase();
+        let value = value.trim();
+        let fields = ["file", "title", "length"];
+        let Some((field, number)) = fields.iter().find_map(|field| {
+            let number = key.strip_prefix(field)?.parse::<u32>().ok()?;
+            Some((*field, number))
+        }) else {
+            continue;
+        };
+        let entry = entries.entry(number).or_default();
+        match field {
+            "file" => {
+                if let Some((location, is_url)) = resolve_location(base_directory, value) {
+                    entry.location = location;
+                    entry.is_url = is_url;
+                }
+            }
+            "title" => entry.title = Some(value.to_string()).filter(|title| !title.is_empty()),
+            _ => {
+                entry.duration = value
+                    .parse::<i64>()
+                    .ok()
+                    .filter(|length| *length >= 0)
+                    .map(|length| length as u64 * 1000)
+            }
+        }
+    }
+    entries
+        .into_values()
+        .filter(|entry| !entry.location.is_empty())
+        .collect()
+}
+
+fn parse_xspf(
+    text: &str,
+    playlist_path: &Path,
+) -> Result<(Option<String>, Vec<PlaylistEntry>), String> {
+    let playlist_url = Url::from_file_path(playlist_path).ok();
+    let mut reader = Reader::from_str(text);
+    let mut elements: Vec<String> = Vec::new();
+    let mut content = String::new();
+    let mut name = None;
+    let mut entries = Vec::new();
+    let mut entry: Option<PlaylistEntry> = None;
+    loop {
+        match reader.read_event().map_err(|e| e.to_string())? {
+            Event::Start(element) => {
+                let element_name =
+                    String::from_utf8_lossy(element.local_name().as_ref()).to_string();
+                if element_name == "track" {
+                    entry = Some(PlaylistEntry::default());
+                }
+                elements.push(element_name);
+                content.clear();
+            }
+            Event::Text(text) => content.push_str(&text.decode().map_err(|e| e.to_string())?),
+            Event::CData(text) => content.push_str(&text.decode().map_err(|e| e.to_string())?),
+            Event::GeneralRef(reference) => {
+                if let Ok(Some(character)) = reference.resolve_char_ref() {
+                    content.push(character);
+                } else if let Some(value) = reference
+                    .decode()
+                    .ok()
+                    .and_then(|entity| resolve_predefined_entity(&entity))
+                {
+                    content.push_str(value);
+                }
+            }
+            Event::End(_) => {
+                let element_name = elements.pop().unwrap_or_default();
+                let value = Some(content.trim().to_string()).filter(|value| !value.is_empty());
+                content.clear();
+                match (elements.last().map(String::as_str), entry.as_mut()) {
+                    (Some("playlist"), _) if element_name == "title" => name = value,
+                    (Some("trackList"), Some(_)) if element_name == "track" => {
+                        let track = entry.take().unwrap_or_default();
+                        if !track.location.is_empty() {
+                            entries.push(track);
+                        }
+                    }
+                    (Some("track"), Some(track)) => match element_name.as_str() {
+                        // A track can list several locations, the first one is preferred
+                        "location" if track.location.is_empty() => {
+                            if let Some((location, is_url)) =
+                                value.and_then(|value| resolve_uri(playlist_url.as_ref(), &value))
+                            {
+                                track.location = location;
+                                track.is_url = is_url;
+                            }
+                        }
+                        "title" => track.title = value,
+                        "creator" => track.artist = value,
+                        "album" => track.album = value,
+                        "duration" => track.duration = value.and_then(|value| value.parse().ok()),
+                        _ => {}
+                    },
+                    _ => {}
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok((name, entries))
+}
+
+/// Reads a playlist file, resolving each entry to an absolute path or a URL.
+pub fn read_playlist(path: &Path) -> Result<Playlist, String> {
+    let format =
+        PlaylistFormat::from_path(path).ok_or_else(|| "Unsupported playlist format".to_string())?;
+    let contents = fs::read(path).map_err(|e| e.to_string())?;
+    // M3U8 is always UTF-8, while M3U files are often in the system's legacy encoding
+    let text = if format == PlaylistFormat::M3u {
+        decode_text(&contents)
+    } else {
+        String::from_utf8_lossy(&contents)
+            .trim_start_matches('\u{feff}')
+            .to_string()
+    };
+    let base_directory = path.parent().unwrap_or(Path::new(""));
+    let (name, entries) = match format {
+        PlaylistFormat::M3u | PlaylistFormat::M3u8 => parse_m3u(&text, base_directory),
+        PlaylistFormat::Pls => (None, parse_pls(&text, base_directory)),
+        PlaylistFormat::Xspf => parse_xspf(&text, path)?,
+    };
+    let name = name.filter(|name| !name.is_empty()).unwrap_or_else(|| {
+        path.file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default()
+    });
+    Ok(Playlist {
+        name,
+        file_path: path.display().to_string(),
+        format,
+        entries,
+    })
+}
+
+/// Reads a playlist without allowing its files to be played, since a playlist can point
+/// anywhere. Entries are only playable once they're matched to tracks in the library.
+#[tauri::command]
+pub fn import_playlist(file_path: String) -> Result<Playlist, String> {
+    read_playlist(Path::new(&file_path))
+}
+
+fn get_display_title(entry: &PlaylistEntry) -> String {
+    let title = entry.title.clone().unwrap_or_else(|| {
+        if entry.is_url {
+            return entry.location.clone();
+        }
+        Path::new(&entry.location)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default()
+    });
+    match &entry.artist {
+        Some(artist) => format!("{} - {}", artist, title),
+        None => title,
+    }
+}
+
+fn write_m3u(name: &str, entries: &[(String, &PlaylistEntry)]) -> String {
+    let mut text = format!("#EXTM3U\n#PLAYLIST:{}\n", name);
+    for (location, entry) in entries {
+        let duration = entry
+            .duration
+            .map_or(-1, |duration| (duration as f64 / 1000.0).round() as i64);
+        text.push_str(&format!(
+            "#EXTINF:{},{}\n",
+            duration,
+            get_display_title(entry)
+        ));
+        if let Some(album) = &entry.album {
+            text.push_str(&format!("#EXTALB:{}\n", album));
+        }
+        text.push_str(location);
+        text.push('\n');
+    }
+    text
+}
+
+fn write_pls(entries: &[(String, &PlaylistEntry)]) -> String {
+    let mut text = "[playlist]\n".to_string();
+    for (index, (location, entry)) in entries.iter().enumerate() {
+        let number = index + 1;
+        let length = entry
+            .duration
+            .map_or(-1, |duration| (duration as f64 / 1000.0).round() as i64);
+        text.push_str(&format!("File{}={}\n", number, location));
+        text.push_str(&format!("Title{}={}\n", number, get_display_title(entry)));
+        text.push_str(&format!("Length{}={}\n", number, length));
+    }
+    text.push_str(&format!("NumberOfEntries={}\nVersion=2\n", entries.len()));
+    text
+}
+
+fn write_xspf(name: &str, entries: &[&PlaylistEntry]) -> Result<String, String> {
+    let mut writer = Writer::new_with_indent(Vec::new(), b' ', 2);
+    writer
+        .write_event(Event::Decl(BytesDecl::new("1.0", Some("UTF-8"), None)))
+        .map_err(|e| e.to_string())?;
+    writer
+        .create_element("playlist")
+        .with_attribute(("version", "1"))
+        .with_attribute(("xmlns", "http://xspf.org/ns/0/"))
+        .write_inner_content(|writer| {
+            writer
+                .create_element("title")
+                .write_text_content(BytesText::new(name))?;
+            writer
+                .create_element("trackList")
+                .write_inner_content(|writer| {
+                    for entry in entries {
+                        let location = if entry.is_url {
+                            Some(entry.location.clone())
+                        } else {
+                            Url::from_file_path(&entry.location)
+                                .ok()
+                                .map(|url| url.to_string())
+                        };
+                        let duration = entry.duration.map(|duration| duration.to_string());
+                        let fields = [
+                            ("location", location.as_ref()),
+                            ("title", entry.title.as_ref()),
+                            ("creator", entry.artist.as_ref()),
+                            ("album", entry.album.as_ref()),
+                            ("duration", duration.as_ref()),
+                        ];
+                        writer
+                            .create_element("track")
+                            .write_inner_content(|writer| {
+                                for (element, value) in fields {
+                                    if let Some(value) = value {
+                                        writer
+                                            .create_element(element)
+                                            .write_text_content(BytesText::new(value))?;
+                                    }
+                                }
+                                Ok(())
+                            })?;
+                    }
+                    Ok(())
+                })?;
+            Ok(())
+        })
+        .map_err(|e| e.to_string())?;
+    String::from_utf8(writer.into_inner()).map_err(|e| e.to_string())
+}
+
+/// Writes `entries` to a playlist file, in the format given by its extension. Local files
+/// are written relative to the playlist's folder in M3U and PLS files unless
+/// `relative_paths` is false, while XSPF always uses absolute `file://` URLs.
+#[tauri::command]
+pub fn export_playlist(
+    file_path: String,
+    name: Option<String>,
+    entries: Vec<PlaylistEntry>,
+    relative_paths: Option<bool>,
+) -> Result<(), String> {
+    let path = Path::new(&file_path);
+    let format =
+        PlaylistFormat::from_path(path).ok_or_else(|| "Unsupported playlist format".to_string())?;
+    let name = name.unwrap_or_else(|| {
+        path.file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default()
+    });
+    // Other players can't address tracks from a cue sheet, so each run of tracks from the
+    // same file is written as a single entry for the whole file
+    let mut file_entries: Vec<PlaylistEntry> = Vec::new();
+    let mut previous_parent: Option<String> = None;
+    for entry in entries {
+        let parent = (!entry.is_url && parse_virtual_track_uri(&entry.location).is_some())
+            .then(|| get_file_path(&entry.location).display().to_string());
+        match (&parent, file_entries.last_mut()) {
+            (Some(parent), Some(file_entry)) if previous_parent.as_ref() == Some(parent) => {
+                file_entry.title = None;
+                file_entry.duration = file_entry
+                    .duration
+                    .zip(entry.duration)
+                    .map(|(total, duration)| total + duration);
+            }
+            _ => file_entries.push(PlaylistEntry {
+                location: parent.clone().unwrap_or(entry.location.clone()),
+                ..entry
+            }),
+        }
+        previous_parent = parent;
+    }
+    let entries = file_entries;
+
+    let contents = if format == PlaylistFormat::Xspf {
+        write_xspf(&name, &entries.iter().collect::<Vec<_>>())?
+    } else {
+        let base_directory = path.parent().unwrap_or(Path::new(""));
+        let entries: Vec<(String, &PlaylistEntry)> = entries
+            .iter()
+            .map(|entry| {
+                let location_path = Path::new(&entry.location);
+                let location = if !entry.is_url && relative_paths.unwrap_or(true) {
+                    get_relative_path(base_directory, location_path)
+                        .map(|relative_path| relative_path.display().to_string())
+                        .unwrap_or_else(|| entry.location.clone())
+                } else {
+                    entry.location.clone()
+                };
+                (location, entry)
+            })
+            .collect();
+        match format {
+            PlaylistFormat::Pls => write_pls(&entries),
+            _ => write_m3u(&name, &entries),
+        }
+    };
+    fs::write(path, contents).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn location(entry: &PlaylistEntry) -> &Path {
+        Path::new(&entry.location)
+    }
+
+    #[test]
+    fn reads_urls_only_with_known_schemes() {
+        let base_directory = Path::new("/music");
+        let (path, is_url) = resolve_location(base_directory, "Live: Side A.mp3").unwrap();
+        assert!(!is_url);
+        assert_eq!(Path::new(&path), Path::new("/music/Live: Side A.mp3"));
+        assert_eq!(
+            resolve_location(base_directory, " http://example.com/stream "),
+            Some(("http://example.com/stream".to_string(), true))
+        );
+        assert_eq!(
+            resolve_location(base_directory, "RTSP:example.com/live"),
+            Some(("RTSP:example.com/live".to_string(), true))
+        );
+        assert!(
+            !resolve_location(base_directory, "C:\\Music\\a.mp3")
+                .unwrap()
+                .1
+        );
+        assert_eq!(resolve_location(base_directory, "  "), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn resolves_file_urls_and_backslashes() {
+        let base_directory = Path::new("/music/lists");
+        assert_eq!(
+            resolve_location(base_directory, "file:///music/a%20b.mp3"),
+            Some(("/music/a b.mp3".to_string(), false))
+        );
+        assert_eq!(
+            resolve_location(base_directory, "..\\albums\\c.flac"),
+            Some(("/music/albums/c.flac".to_string(), false))
+        );
+    }
+
+    #[test]
+    fn parses_m3u() {
+        let text = "#EXTM3U
+#PLAYLIST:Road Trip
+#EXTINF:245,Artist - Title
+#EXTALB:Album
+songs/one.mp3
+
+#EXTINF:-1 tvg-name=\"News, Weather\",Radio
+http://example.com/stream
+../two.flac
+";
+        let (name, entries) = parse_m3u(text, Path::new("/music/lists"));
+        assert_eq!(name.as_deref(), Some("Road Trip"));
+        assert_eq!(entries.len(), 3);
+        assert_eq!(
+            location(&entries[0]),
+            Path::new("/music/lists/songs/one.mp3")
+        );
+        assert_eq!(entries[0].artist.as_deref(), Some("Artist"));
+        assert_eq!(entries[0].title.as_deref(), Some("Title"));
+        assert_eq!(entries[0].album.as_deref(), Some("Album"));
+        assert_eq!(entries[0].duration, Some(245_000));
+        assert!(entries[1].is_url);
+        assert_eq!(entries[1].title.as_deref(), Some("Radio"));
+        assert_eq!(entries[1].duration, None);
+        assert_eq!(location(&entries[2]), Path::new("/music/two.flac"));
+        assert_eq!(entries[2].title, None);
+        assert_eq!(entries[2].album, None);
+    }
+
+    #[test]
+    fn parses_pls_in_entry_order() {
+        let text = "[playlist]
+File2=b.mp3
+Title2=Second
+Length2=61
+File1=http://example.com/stream
+Title1=
+Length1=-1
+Title3=Missing file
+NumberOfEntries=3
+Version=2
+";
+        let entries = parse_pls(text, Path::new("/music"));
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].location, "http://example.com/stream");
+        assert!(entries[0].is_url);
+        assert_eq!(entries[0].title, None);
+        assert_eq!(entries[0].duration, None);
+        assert_eq!(location(&entries[1]), Path::new("/music/b.mp3"));
+        assert_eq!(entries[1].title.as_deref(), Some("Second"));
+        assert_eq!(entries[1].duration, Some(61_000));
+    }
+
+    #[test]
+    fn parses_xspf() {
+        let text = r#"<?xml version="1.0" encoding="UTF-8"?>
+<playlist version="1" xmlns="http://xspf.org/ns/0/">
+  <title>Mix &amp; Match</title>
+  <trackList>
+    <track>
+      <location>http://example.com/a.mp3</location>
+      <location>http://example.com/b.mp3</location>
+      <title>A</title>
+      <creator>Artist</creator>
+      <album><![CDATA[Album]]></album>
+      <duration>1000</duration>
+    </track>
+    <track>
+      <title>No location</title>
+    </track>
+  </trackList>
+</playlist>
+"#;
+        let (name, entries) = parse_xspf(text, Path::new("list.xspf")).unwrap();
+        assert_eq!(name.as_deref(), Some("Mix & Match"));
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].location, "http://example.com/a.mp3");
+        assert!(entries[0].is_url);
+        assert_eq!(entries[0].title.as_deref(), Some("A"));
+        assert_eq!(entries[0].artist.as_deref(), Some("Artist"));
+        assert_eq!(entries[0].album.as_deref(), Some("Album"));
+        assert_eq!(entries[0].duration, Some(1000));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn resolves_xspf_locations_relative_to_the_playlist() {
+        let text = "<playlist><trackList><track><location>songs/a%20b.mp3</location></track>\
+            </trackList></playlist>";
+        let (_, entries) = parse_xspf(text, Path::new("/music/list.xspf")).unwrap();
+        assert_eq!(entries[0].location, "/music/songs/a b.mp3");
+        assert!(!entries[0].is_url);
+    }
+
+    #[test]
+    fn finds_relative_paths() {
+        let base_directory = Path::new("/music/lists");
+        assert_eq!(
+            get_relative_path(base_directory, Path::new("/music/lists/a.mp3")),
+            Some(PathBuf::from("a.mp3"))
+        );
+        assert_eq!(
+            get_relative_path(base_directory, Path::new("/music/albums/b.mp3")),
+            Some(PathBuf::from("../albums/b.mp3"))
+        );
+        assert_eq!(
+            get_relative_path(base_directory, Path::new("/other/c.mp3")),
+            Some(PathBuf::from("../../other/c.mp3"))
+        );
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn has_no_relative_path_across_drives() {
+        assert_eq!(
+            get_relative_path(Path::new("C:\\music"), Path::new("D:\\a.mp3")),
+            None
+        );
+    }
+}
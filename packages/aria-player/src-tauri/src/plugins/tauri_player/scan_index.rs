@@ -0,0 +1,118 @@
+use super::cue_sheet::get_file_path;
+use super::scanner::ScanError;
+use super::{get_audio_files_from_directory, get_file_stats, sha256_hex};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Manager};
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ScanIndexEntry {
+    pub date_modified: u64,
+    pub file_size: u64,
+}
+
+/// The files found in a library root by the previous scan, keyed by path.
+#[derive(Serialize, Deserialize, Default)]
+pub struct ScanIndex {
+    pub entries: HashMap<String, ScanIndexEntry>,
+}
+
+#[derive(Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryChanges {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub modified: Vec<String>,
+    pub errors: Vec<ScanError>,
+}
+
+fn get_scan_index_path(app: &AppHandle, directory_path: &Path) -> Result<PathBuf, String> {
+    let app_data_dir = app
+        .path()
+        .app_data_dir()
+        .map_err(|_| "Couldn't get app data directory".to_string())?;
+    let root_hash = sha256_hex(directory_path.to_string_lossy().as_bytes());
+    Ok(app_data_dir
+        .join(".scan-index")
+        .join(format!("{}.json", root_hash)))
+}
+
+pub fn load_scan_index(app: &AppHandle, directory_path: &Path) -> Result<ScanIndex, String> {
+    let index_path = get_scan_index_path(app, directory_path)?;
+    match fs::read(&index_path) {
+        Ok(contents) => Ok(serde_json::from_slice(&contents).unwrap_or_default()),
+        Err(_) => Ok(ScanIndex::default()),
+    }
+}
+
+pub fn save_scan_index(
+    app: &AppHandle,
+    directory_path: &Path,
+    index: &ScanIndex,
+) -> Result<(), String> {
+    let index_path = get_scan_index_path(app, directory_path)?;
+    if let Some(parent) = index_path.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    let contents = serde_json::to_vec(index).map_err(|e| e.to_string())?;
+    let temp_path = index_path.with_extension("json.tmp");
+    fs::write(&temp_path, contents).map_err(|e| e.to_string())?;
+    fs::rename(&temp_path, &index_path).map_err(|e| e.to_string())
+}
+
+/// Compares the files currently in `directory_path` with the previous scan
+/// and stores the new state, so each call only reports what changed since the last one.
+#[tauri::command]
+pub fn get_library_changes(
+    app: AppHandle,
+    directory_path: &Path,
+) -> Result<LibraryChanges, String> {
+    let scan = get_audio_files_from_directory(app.clone(), directory_path);
+    let mut previous_index = load_scan_index(&app, directory_path)?;
+    let mut index = ScanIndex::default();
+    let mut changes = LibraryChanges::default();
+
+    for file in scan.files {
+        let Ok(stats) = get_file_stats(get_file_path(&file)) else {
+            continue;
+        };
+        let entry = ScanIndexEntry {
+            date_modified: stats.date_modified,
+            file_size: stats.file_size,
+        };
+        match previous_index.entries.remove(&file) {
+            Some(previous_entry) if previous_entry == entry => {}
+            Some(_) => changes.modified.push(file.clone()),
+            None => changes.added.push(file.clone()),
+        }
+        index.entries.insert(file, entry);
+    }
+    for (file, entry) in previous_index.entries {
+        // Files under folders that couldn't be read this time are kept rather than reported as removed
+        let skipped = scan
+            .errors
+            .iter()
+            .any(|error| Path::new(&file).starts_with(&error.path));
+        if skipped {
+            index.entries.insert(file, entry);
+        } else {
+            changes.removed.push(file);
+        }
+    }
+    changes.errors = scan.errors;
+
+    save_scan_index(&app, directory_path, &index)?;
+    Ok(changes)
+}
+
+#[tauri::command]
+pub fn clear_scan_index(app: AppHandle, directory_path: &Path) -> Result<(), String> {
+    let index_path = get_scan_index_path(&app, directory_path)?;
+    if index_path.exists() {
+        fs::remove_file(index_path).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
@@ -0,0 +1,534 @@
+use super::cue_sheet::get_cue_track_uris;
+use super::tag_editor::TEMP_FILE_SUFFIX;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use lofty::probe::Probe;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, DirEntry};
+use std::io::ErrorKind;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tauri::ipc::Channel;
+use tauri::{AppHandle, Manager};
+use tauri_plugin_store::StoreExt;
+
+const SCAN_CHUNK_SIZE: usize = 250;
+const SCAN_CHUNK_INTERVAL: Duration = Duration::from_millis(200);
+const DIRECTORY_READ_TIMEOUT: Duration = Duration::from_secs(30);
+/// How many folder reads can be left waiting on unresponsive drives before scans stop
+/// starting new ones.
+const MAX_STALLED_DIRECTORY_READS: usize = 8;
+
+/// Folder reads that timed out and still haven't returned, across all scans.
+static STALLED_DIRECTORY_READS: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase", tag = "event", content = "data")]
+pub enum ScanEvent {
+    #[serde(rename_all = "camelCase")]
+    Chunk { paths: Vec<String> },
+    #[serde(rename_all = "camelCase")]
+    Finished {
+        total: usize,
+        errors: Vec<ScanError>,
+    },
+}
+
+#[derive(Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ScanErrorKind {
+    PermissionDenied,
+    BrokenSymlink,
+    Unreadable,
+    Timeout,
+}
+
+/// A path that was skipped during a scan, so users can see why tracks are missing.
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScanError {
+    pub path: String,
+    pub kind: ScanErrorKind,
+    pub message: String,
+}
+
+impl ScanError {
+    fn new(path: &Path, kind: ScanErrorKind, message: impl ToString) -> Self {
+        Self {
+            path: path.display().to_string(),
+            kind,
+            message: message.to_string(),
+        }
+    }
+
+    fn from_io_error(path: &Path, error: &std::io::Error) -> Self {
+        let kind = match error.kind() {
+            ErrorKind::PermissionDenied => ScanErrorKind::PermissionDenied,
+            ErrorKind::TimedOut => ScanErrorKind::Timeout,
+            _ => ScanErrorKind::Unreadable,
+        };
+        Self::new(path, kind, error)
+    }
+}
+
+#[derive(Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct AudioFilesScan {
+    pub files: Vec<String>,
+    pub errors: Vec<ScanError>,
+}
+
+pub const DEFAULT_AUDIO_EXTENSIONS: &[&str] = &[
+    "mp3", "wav", "flac", "ogg", "oga", "opus", "m4a", "m4b", "aac", "aiff", "aif", "wv", "ape",
+    "mpc", "dsf",
+];
+
+pub const DEFAULT_EXCLUDE_PATTERNS: &[&str] = &[
+    "@eaDir/",
+    ".Trash*/",
+    ".stversions/",
+    "$RECYCLE.BIN/",
+    "System Volume Information/",
+];
+
+const IGNORE_MARKER_FILES: &[&str] = &[".nomedia", ".ariaignore"];
+
+pub struct ScanOptions {
+    pub extensions: HashSet<String>,
+    pub detect_unknown_files: bool,
+    pub skip_hidden_folders: bool,
+    pub exclude_patterns: Vec<String>,
+    pub library_excludes: HashMap<String, Vec<String>>,
+    pub split_cue_sheets: bool,
+}
+
+impl ScanOptions {
+    pub fn from_config(app: &AppHandle) -> Self {
+        let store = app.store(PathBuf::from(".app-config")).unwrap();
+        let extensions = store
+            .get("audioextensions")
+            .and_then(|val| {
+                val.as_array().map(|extensions| {
+                    extensions
+                        .iter()
+                        .filter_map(|ext| ext.as_str())
+                        .map(|ext| ext.trim_start_matches('.').to_lowercase())
+                        .collect()
+                })
+            })
+            .unwrap_or_else(|| {
+                DEFAULT_AUDIO_EXTENSIONS
+                    .iter()
+                    .map(|ext| ext.to_string())
+                    .collect()
+            });
+        let detect_unknown_files = store
+            .get("detectunknownaudiofiles")
+            .and_then(|val| val.as_bool())
+            .unwrap_or(false);
+        let skip_hidden_folders = store
+            .get("skiphiddenfolders")
+            .and_then(|val| val.as_bool())
+            .unwrap_or(true);
+        let exclude_patterns = store
+            .get("excludepatterns")
+            .and_then(|val| serde_json::from_value(val).ok())
+            .unwrap_or_else(|| {
+                DEFAULT_EXCLUDE_PATTERNS
+                    .iter()
+                    .map(|pattern| pattern.to_string())
+                    .collect()
+            });
+        let library_excludes = store
+            .get("libraryexcludes")
+            .and_then(|val| serde_json::from_value(val).ok())
+            .unwrap_or_default();
+        let split_cue_sheets = store
+            .get("splitcuesheets")
+            .and_then(|val| val.as_bool())
+            .unwrap_or(true);
+        Self {
+            extensions,
+            detect_unknown_files,
+            skip_hidden_folders,
+            exclude_patterns,
+            library_excludes,
+            split_cue_sheets,
+        }
+    }
+
+    /// Combines the global exclude patterns with the ones set for this library root.
+    pub fn filter_for_root(&self, root: &Path) -> LibraryFilter {
+        let mut builder = GitignoreBuilder::new(root);
+        let root_patterns = self.library_excludes.get(&root.display().to_string());
+        for pattern in self
+            .exclude_patterns
+            .iter()
+            .chain(root_patterns.into_iter().flatten())
+        {
+            if let Err(err) = builder.add_line(None, pattern) {
+                eprintln!("Invalid exclude pattern '{}': {}", pattern, err);
+            }
+        }
+        LibraryFilter {
+            root: root.to_path_buf(),
+            gitignore: builder.build().unwrap_or_else(|_| Gitignore::empty()),
+            skip_hidden_folders: self.skip_hidden_folders,
+        }
+    }
+
+    pub fn has_audio_extension(&self, path: &Path) -> bool {
+        path.extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|ext| self.extensions.contains(&ext.to_lowercase()))
+    }
+
+    pub fn is_audio_file(&self, path: &Path) -> bool {
+        if self.has_audio_extension(path) {
+            return true;
+        }
+        self.detect_unknown_files && !is_temp_file(path) && is_detected_audio_file(path)
+    }
+}
+
+pub struct LibraryFilter {
+    root: PathBuf,
+    gitignore: Gitignore,
+    skip_hidden_folders: bool,
+}
+
+impl LibraryFilter {
+    pub fn is_excluded_directory(&self, path: &Path) -> bool {
+        (self.skip_hidden_folders && is_hidden(path))
+            || self.gitignore.matched(path, true).is_ignore()
+            || IGNORE_MARKER_FILES
+                .iter()
+                .any(|marker| path.join(marker).exists())
+    }
+
+    pub fn is_excluded_file(&self, path: &Path) -> bool {
+        self.gitignore.matched(path, false).is_ignore()
+    }
+
+    /// Checks a path found outside of a walk, such as one reported by the watcher,
+    /// against the exclusion rules of every folder between it and the library root.
+    pub fn is_excluded_path(&self, path: &Path) -> bool {
+        if !path.starts_with(&self.root) {
+            return false;
+        }
+        let excluded_parent = path
+            .ancestors()
+            .skip(1)
+            .take_while(|ancestor| *ancestor != self.root)
+            .any(|ancestor| self.is_excluded_directory(ancestor));
+        if excluded_parent {
+            return true;
+        }
+        if path.is_dir() {
+            self.is_excluded_directory(path)
+        } else {
+            self.is_excluded_file(path)
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn is_hidden(path: &Path) -> bool {
+    use std::os::windows::fs::MetadataExt;
+    const FILE_ATTRIBUTE_HIDDEN: u32 = 0x2;
+    let has_dot_prefix = path
+        .file_name()
+        .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+    has_dot_prefix
+        || fs::metadata(path)
+            .is_ok_and(|meta| (meta.file_attributes() & FILE_ATTRIBUTE_HIDDEN) != 0)
+}
+
+#[cfg(not(target_os = "windows"))]
+fn is_hidden(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| name.to_string_lossy().starts_with('.'))
+}
+
+#[cfg(unix)]
+type DirectoryId = (u64, u64);
+
+#[cfg(not(unix))]
+type DirectoryId = PathBuf;
+
+/// Identifies the real directory behind a path, so symlinked folders are only walked once.
+#[cfg(unix)]
+fn get_directory_id(path: &Path) -> Option<DirectoryId> {
+    use std::os::unix::fs::MetadataExt;
+    fs::metadata(path).ok().map(|meta| (meta.dev(), meta.ino()))
+}
+
+#[cfg(not(unix))]
+fn get_directory_id(path: &Path) -> Option<DirectoryId> {
+    fs::canonicalize(path).ok()
+}
+
+pub fn is_temp_file(path: &Path) -> bool {
+    path.to_string_lossy().ends_with(TEMP_FILE_SUFFIX)
+}
+
+/// Checks the file's contents with lofty, for audio files with missing or unrecognised extensions.
+fn is_detected_audio_file(path: &Path) -> bool {
+    Probe::open(path)
+        .and_then(|probe| Ok(probe.guess_file_type()?))
+        .is_ok_and(|probe| probe.file_type().is_some())
+}
+
+#[derive(PartialEq)]
+enum DirectoryReadState {
+    Reading,
+    Stalled,
+    Finished,
+}
+
+/// Reads a directory on a separate thread, so a stalled network mount can't hang the whole scan.
+/// Entries that can't be read are returned as errors alongside the ones that can.
+///
+/// A read that times out can't be cancelled, so its thread stays blocked until the drive
+/// responds. To keep those threads from piling up, new reads fail straight away while
+/// [`MAX_STALLED_DIRECTORY_READS`] are still waiting. Reads that are merely in progress
+/// don't count towards the limit, so concurrent scans of healthy folders aren't affected.
+fn read_dir_with_timeout(
+    directory_path: &Path,
+) -> Result<(Vec<DirEntry>, Vec<ScanError>), ScanError> {
+    let timeout_error =
+        |message: String| ScanError::new(directory_path, ScanErrorKind::Timeout, message);
+    if STALLED_DIRECTORY_READS.load(Ordering::SeqCst) >= MAX_STALLED_DIRECTORY_READS {
+        return Err(timeout_error(
+            "Too many folders are still waiting on drives that aren't responding".to_string(),
+        ));
+    }
+    let state = Arc::new(Mutex::new(DirectoryReadState::Reading));
+    let thread_state = state.clone();
+    let (tx, rx) = mpsc::channel();
+    let thread_path = directory_path.to_path_buf();
+    thread::spawn(move || {
+        let result = fs::read_dir(&thread_path)
+            .map(|read_dir| read_dir.collect::<Vec<std::io::Result<DirEntry>>>());
+        let mut state = thread_state.lock().unwrap();
+        if *state == DirectoryReadState::Stalled {
+            STALLED_DIRECTORY_READS.fetch_sub(1, Ordering::SeqCst);
+        }
+        *state = DirectoryReadState::Finished;
+        drop(state);
+        let _ = tx.send(result);
+    });
+    let result = match rx.recv_timeout(DIRECTORY_READ_TIMEOUT) {
+        Ok(result) => result,
+        Err(_) => {
+            let timeout_message = format!(
+                "Reading the folder took longer than {} seconds",
+                DIRECTORY_READ_TIMEOUT.as_secs()
+            );
+            let mut state = state.lock().unwrap();
+            if *state == DirectoryReadState::Reading {
+                *state = DirectoryReadState::Stalled;
+                STALLED_DIRECTORY_READS.fetch_add(1, Ordering::SeqCst);
+                return Err(timeout_error(timeout_message));
+            }
+            drop(state);
+            // The read finished just as it timed out, so its result is on its way
+            rx.recv().map_err(|_| timeout_error(timeout_message))?
+        }
+    };
+    match result {
+        Ok(results) => {
+            let mut entries = Vec::with_capacity(results.len());
+            let mut errors = Vec::new();
+            for result in results {
+                match result {
+                    Ok(entry) => entries.push(entry),
+                    Err(error) => errors.push(ScanError::from_io_error(directory_path, &error)),
+                }
+            }
+            Ok((entries, errors))
+        }
+        Err(error) => Err(ScanError::from_io_error(directory_path, &error)),
+    }
+}
+
+/// Asks the system whether the file can be read, which accounts for its owner, group and
+/// any ACLs without opening it, since opening every file would slow scans down and make
+/// cloud storage download placeholder files. Anything else that stops a file from being
+/// read is reported when its metadata is read.
+#[cfg(unix)]
+fn check_readable(path: &Path) -> Result<(), ScanError> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    let Ok(c_path) = CString::new(path.as_os_str().as_bytes()) else {
+        return Ok(());
+    };
+    if unsafe { libc::access(c_path.as_ptr(), libc::R_OK) } != 0 {
+        return Err(ScanError::from_io_error(
+            path,
+            &std::io::Error::last_os_error(),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn check_readable(_path: &Path) -> Result<(), ScanError> {
+    Ok(())
+}
+
+/// Returns the audio file, or a virtual track for each of its tracks if it has a cue sheet.
+/// `check_sidecar` can be turned off when the file's folder has no `.cue` files.
+pub fn get_track_uris(path: &Path, options: &ScanOptions, check_sidecar: bool) -> Vec<PathBuf> {
+    if options.split_cue_sheets {
+        if let Some(uris) = get_cue_track_uris(path, check_sidecar) {
+            return uris.into_iter().map(PathBuf::from).collect();
+        }
+    }
+    vec![path.to_path_buf()]
+}
+
+struct Walker<'a> {
+    options: &'a ScanOptions,
+    filter: &'a LibraryFilter,
+    visited_directories: HashSet<DirectoryId>,
+    errors: Vec<ScanError>,
+    on_file: &'a mut dyn FnMut(PathBuf),
+}
+
+impl Walker<'_> {
+    fn walk(&mut self, directory_path: &Path) {
+        if let Some(directory_id) = get_directory_id(directory_path) {
+            if !self.visited_directories.insert(directory_id) {
+                return;
+            }
+        }
+        let entries = match read_dir_with_timeout(directory_path) {
+            Ok((entries, errors)) => {
+                self.errors.extend(errors);
+                entries
+            }
+            Err(error) => {
+                self.errors.push(error);
+                return;
+            }
+        };
+        let has_cue_files = entries.iter().any(|entry| {
+            Path::new(&entry.file_name())
+                .extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("cue"))
+        });
+        for entry in entries {
+            let path = entry.path();
+            let mut metadata = match entry.metadata() {
+                Ok(metadata) => metadata,
+                Err(error) => {
+                    self.errors.push(ScanError::from_io_error(&path, &error));
+                    continue;
+                }
+            };
+            if metadata.file_type().is_symlink() {
+                metadata = match fs::metadata(&path) {
+                    Ok(metadata) => metadata,
+                    Err(error) if error.kind() == ErrorKind::NotFound => {
+                        self.errors.push(ScanError::new(
+                            &path,
+                            ScanErrorKind::BrokenSymlink,
+                            error,
+                        ));
+                        continue;
+                    }
+                    Err(error) => {
+                        self.errors.push(ScanError::from_io_error(&path, &error));
+                        continue;
+                    }
+                };
+            }
+            if metadata.is_dir() {
+                if !self.filter.is_excluded_directory(&path) {
+                    self.walk(&path);
+                }
+            } else if metadata.is_file()
+                && !self.filter.is_excluded_file(&path)
+                && self.options.is_audio_file(&path)
+            {
+                match check_readable(&path) {
+                    Ok(()) => {
+                        for uri in get_track_uris(&path, self.options, has_cue_files) {
+                            (self.on_file)(uri);
+                        }
+                    }
+                    Err(error) => self.errors.push(error),
+                }
+            }
+        }
+    }
+}
+
+/// Recursively walks `directory_path`, calling `on_file` for every audio file as soon as it's found.
+/// Folders and files that can't be read are skipped and returned as errors.
+pub fn walk_audio_files(
+    directory_path: &Path,
+    options: &ScanOptions,
+    on_file: &mut dyn FnMut(PathBuf),
+) -> Vec<ScanError> {
+    let filter = options.filter_for_root(directory_path);
+    walk_audio_files_with_filter(directory_path, options, &filter, on_file)
+}
+
+/// Walks a folder inside a library root using the root's exclusion rules.
+pub fn walk_audio_files_with_filter(
+    directory_path: &Path,
+    options: &ScanOptions,
+    filter: &LibraryFilter,
+    on_file: &mut dyn FnMut(PathBuf),
+) -> Vec<ScanError> {
+    if !directory_path.is_dir() {
+        return Vec::new();
+    }
+    let mut walker = Walker {
+        options,
+        filter,
+        visited_directories: HashSet::new(),
+        errors: Vec::new(),
+        on_file,
+    };
+    walker.walk(directory_path);
+    walker.errors
+}
+
+/// Scans `directory_path` on a background thread, sending discovered files through `on_event`
+/// in chunks while the walk is still running, followed by a `finished` summary.
+#[tauri::command]
+pub fn scan_directory_streamed(
+    app: AppHandle,
+    directory_path: PathBuf,
+    on_event: Channel<ScanEvent>,
+) {
+    let _ = app
+        .asset_protocol_scope()
+        .allow_directory(&directory_path, true);
+    let options = ScanOptions::from_config(&app);
+    thread::spawn(move || {
+        let mut chunk = Vec::with_capacity(SCAN_CHUNK_SIZE);
+        let mut last_sent = Instant::now();
+        let mut total = 0;
+        let errors = walk_audio_files(&directory_path, &options, &mut |path| {
+            chunk.push(path.display().to_string());
+            total += 1;
+            if chunk.len() >= SCAN_CHUNK_SIZE || last_sent.elapsed() >= SCAN_CHUNK_INTERVAL {
+                let paths = mem::replace(&mut chunk, Vec::with_capacity(SCAN_CHUNK_SIZE));
+                let _ = on_event.send(ScanEvent::Chunk { paths });
+                last_sent = Instant::now();
+            }
+        });
+        if !chunk.is_empty() {
+            let _ = on_event.send(ScanEvent::Chunk { paths: chunk });
+        }
+        let _ = on_event.send(ScanEvent::Finished { total, errors });
+    });
+}
@@ -0,0 +1,221 @@
+use super::track_metadata::TrackMetadata;
+use super::{get_metadata, is_placeholder_file};
+use lofty::config::WriteOptions;
+use lofty::prelude::{Accessor, ItemKey, TagExt, TaggedFileExt};
+use lofty::probe::Probe;
+use lofty::tag::{ItemValue, Tag, TagItem};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tauri::AppHandle;
+
+/// Suffix for the copies tags are written to before they replace the original file.
+pub const TEMP_FILE_SUFFIX: &str = ".aria-tmp";
+
+/// Tag values to write, using the same field names as `get_metadata`.
+///
+/// Fields left as `None` are not changed. Empty strings, empty lists and
+/// a track or disc number of 0 remove the value from the file.
+#[derive(Serialize, Deserialize, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TagChanges {
+    pub title: Option<String>,
+    pub artist: Option<Vec<String>>,
+    pub album: Option<String>,
+    pub album_artist: Option<String>,
+    pub track: Option<u32>,
+    pub disc: Option<u32>,
+    pub year: Option<String>,
+    pub genre: Option<Vec<String>>,
+    pub composer: Option<Vec<String>>,
+    pub comments: Option<Vec<String>>,
+}
+
+fn set_text(tag: &mut Tag, key: ItemKey, value: &str) {
+    if value.is_empty() {
+        tag.remove_key(key);
+    } else {
+        tag.insert_text(key, value.to_string());
+    }
+}
+
+fn set_strings(tag: &mut Tag, key: ItemKey, values: &[String]) {
+    tag.remove_key(key);
+    for value in values.iter().filter(|value| !value.is_empty()) {
+        tag.push(TagItem::new(key, ItemValue::Text(value.clone())));
+    }
+}
+
+pub fn apply_tag_changes(tag: &mut Tag, changes: &TagChanges) {
+    if let Some(title) = &changes.title {
+        set_text(tag, ItemKey::TrackTitle, title);
+    }
+    if let Some(artists) = &changes.artist {
+        set_strings(tag, ItemKey::TrackArtist, artists);
+    }
+    if let Some(album) = &changes.album {
+        set_text(tag, ItemKey::AlbumTitle, album);
+    }
+    if let Some(album_artist) = &changes.album_artist {
+        set_text(tag, ItemKey::AlbumArtist, album_artist);
+    }
+    match changes.track {
+        Some(0) => tag.remove_track(),
+        Some(track) => tag.set_track(track),
+        None => {}
+    }
+    match changes.disc {
+        Some(0) => tag.remove_disk(),
+        Some(disc) => tag.set_disk(disc),
+        None => {}
+    }
+    if let Some(year) = &changes.year {
+        // get_metadata prefers the recording date, so a leftover year would never be shown
+        tag.remove_key(ItemKey::Year);
+        set_text(tag, ItemKey::RecordingDate, year);
+    }
+    if let Some(genres) = &changes.genre {
+        set_strings(tag, ItemKey::Genre, genres);
+    }
+    if let Some(composers) = &changes.composer {
+        set_strings(tag, ItemKey::Composer, composers);
+    }
+    if let Some(comments) = &changes.comments {
+        set_strings(tag, ItemKey::Comment, comments);
+    }
+}
+
+fn get_text(tag: &Tag, key: ItemKey) -> String {
+    tag.get_string(key).unwrap_or_default().to_string()
+}
+
+fn get_strings(tag: &Tag, key: ItemKey) -> Vec<String> {
+    tag.get_strings(key)
+        .map(|value| value.to_string())
+        .collect()
+}
+
+/// Reads the current values of every field set in `changes`, so writing them back reverts the edit.
+pub fn read_tag_values(tag: &Tag, changes: &TagChanges) -> TagChanges {
+    TagChanges {
+        title: changes
+            .title
+            .as_ref()
+            .map(|_| get_text(tag, ItemKey::TrackTitle)),
+        artist: changes
+            .artist
+            .as_ref()
+            .map(|_| get_strings(tag, ItemKey::TrackArtist)),
+        album: changes
+            .album
+            .as_ref()
+            .map(|_| get_text(tag, ItemKey::AlbumTitle)),
+        album_artist: changes
+            .album_artist
+            .as_ref()
+            .map(|_| get_text(tag, ItemKey::AlbumArtist)),
+        track: changes.track.map(|_| tag.track().unwrap_or_default()),
+        disc: changes.disc.map(|_| tag.disk().unwrap_or_default()),
+        year: changes.year.as_ref().map(|_| {
+            tag.get_string(ItemKey::RecordingDate)
+                .or_else(|| tag.get_string(ItemKey::Year))
+                .unwrap_or_default()
+                .to_string()
+        }),
+        genre: changes
+            .genre
+            .as_ref()
+            .map(|_| get_strings(tag, ItemKey::Genre)),
+        composer: changes
+            .composer
+            .as_ref()
+            .map(|_| get_strings(tag, ItemKey::Composer)),
+        comments: changes
+            .comments
+            .as_ref()
+            .map(|_| get_strings(tag, ItemKey::Comment)),
+    }
+}
+
+/// Reads the file's primary tag, or creates an empty one if the file doesn't have one yet.
+pub fn read_primary_tag(path: &Path) -> Result<Tag, String> {
+    if is_placeholder_file(path) {
+        return Err("placeholder_file".to_string());
+    }
+    let tagged_file = match Probe::open(path) {
+        Ok(file) => file.read().map_err(|e| e.to_string())?,
+        Err(_) => return Err("Failed to probe the file".to_string()),
+    };
+    Ok(tagged_file
+        .primary_tag()
+        .cloned()
+        .unwrap_or_else(|| Tag::new(tagged_file.primary_tag_type())))
+}
+
+/// Counts temporary files, so writes to the same file at the same time don't share one.
+static TEMP_FILE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// A hidden path next to `path` that no other write is using.
+pub fn get_temp_path(path: &Path) -> Result<PathBuf, String> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| "Invalid file path".to_string())?;
+    Ok(path.with_file_name(format!(
+        ".{}.{}-{}{}",
+        file_name.to_string_lossy(),
+        process::id(),
+        TEMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed),
+        TEMP_FILE_SUFFIX
+    )))
+}
+
+/// Writes `tag` to a copy of the file and renames it over the original,
+/// so an interrupted write can never leave a half-written audio file behind.
+///
+/// The file's modified date is updated like any other edit, unless
+/// `preserve_date_modified` is set, in which case the original date is restored.
+pub fn save_tag_atomically(
+    path: &Path,
+    tag: &Tag,
+    preserve_date_modified: bool,
+) -> Result<(), String> {
+    let temp_path = get_temp_path(path)?;
+    let original_modified = fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .map_err(|e| e.to_string())?;
+    fs::copy(path, &temp_path).map_err(|e| e.to_string())?;
+    let result = tag
+        .save_to_path(&temp_path, WriteOptions::default())
+        .map_err(|e| e.to_string())
+        .and_then(|_| {
+            if preserve_date_modified {
+                fs::File::options()
+                    .write(true)
+                    .open(&temp_path)
+                    .and_then(|file| file.set_modified(original_modified))
+                    .map_err(|e| e.to_string())?;
+            }
+            fs::rename(&temp_path, path).map_err(|e| e.to_string())
+        });
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+/// Writes the given tag values to the file and returns its updated metadata.
+#[tauri::command]
+pub fn set_metadata(
+    app: AppHandle,
+    file_path: String,
+    changes: TagChanges,
+    preserve_date_modified: Option<bool>,
+) -> Result<TrackMetadata, String> {
+    let path = Path::new(&file_path);
+    let mut tag = read_primary_tag(path)?;
+    apply_tag_changes(&mut tag, &changes);
+    save_tag_atomically(path, &tag, preserve_date_modified.unwrap_or(false))?;
+    get_metadata(app, file_path)
+}
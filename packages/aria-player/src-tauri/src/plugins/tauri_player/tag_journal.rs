@@ -0,0 +1,282 @@
+use super::get_metadata;
+use super::tag_editor::{
+    apply_tag_changes, read_primary_tag, read_tag_values, save_tag_atomically, TagChanges,
+};
+use super::track_metadata::TrackMetadata;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Manager};
+
+/// Tells apart journals created in the same millisecond.
+static JOURNAL_COUNTER: AtomicU32 = AtomicU32::new(0);
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TagEdit {
+    pub file_path: String,
+    pub changes: TagChanges,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TagEditResult {
+    pub file_path: String,
+    pub metadata: Option<TrackMetadata>,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TagBatchResult {
+    /// Identifies the undo journal for this batch, or `None` if no files were changed.
+    pub journal_id: Option<String>,
+    pub results: Vec<TagEditResult>,
+}
+
+/// The tag values a batch edit replaced, so the edit can be reverted later.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TagJournal {
+    pub id: String,
+    pub date_created: u64,
+    pub entries: Vec<TagJournalEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TagJournalEntry {
+    pub file_path: String,
+    pub previous_values: TagChanges,
+    /// Entries are recorded before their file is written and marked as applied after,
+    /// so a batch that was interrupted can still be undone.
+    #[serde(default = "default_applied")]
+    pub applied: bool,
+}
+
+fn default_applied() -> bool {
+    true
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TagJournalSummary {
+    pub id: String,
+    pub date_created: u64,
+    pub file_count: usize,
+}
+
+fn get_journal_dir(app: &AppHandle) -> Result<PathBuf, String> {
+    app.path()
+        .app_data_dir()
+        .map(|path| path.join(".tag-journal"))
+        .map_err(|_| "Couldn't get app data directory".to_string())
+}
+
+fn get_journal_path(app: &AppHandle, journal_id: &str) -> Result<PathBuf, String> {
+    if journal_id.is_empty() || !journal_id.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Err("Invalid journal ID".to_string());
+    }
+    Ok(get_journal_dir(app)?.join(format!("{}.json", journal_id)))
+}
+
+fn save_journal(app: &AppHandle, journal: &TagJournal) -> Result<(), String> {
+    let journal_path = get_journal_path(app, &journal.id)?;
+    if let Some(parent) = journal_path.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    let contents = serde_json::to_vec(journal).map_err(|e| e.to_string())?;
+    // Replaced in one step, so an interrupted save can't leave a broken journal
+    let temp_path = journal_path.with_extension("json.tmp");
+    fs::write(&temp_path, contents).map_err(|e| e.to_string())?;
+    fs::rename(temp_path, journal_path).map_err(|e| e.to_string())
+}
+
+fn load_journal(app: &AppHandle, journal_id: &str) -> Result<TagJournal, String> {
+    let contents = fs::read(get_journal_path(app, journal_id)?).map_err(|e| e.to_string())?;
+    serde_json::from_slice(&contents).map_err(|e| e.to_string())
+}
+
+fn write_tag_changes(
+    path: &Path,
+    changes: &TagChanges,
+    preserve_date_modified: bool,
+) -> Result<(), String> {
+    let mut tag = read_primary_tag(path)?;
+    apply_tag_changes(&mut tag, changes);
+    save_tag_atomically(path, &tag, preserve_date_modified)
+}
+
+fn to_result(app: &AppHandle, file_path: String, result: Result<(), String>) -> TagEditResult {
+    match result.and_then(|_| get_metadata(app.clone(), file_path.clone())) {
+        Ok(metadata) => TagEditResult {
+            file_path,
+            metadata: Some(metadata),
+            error: None,
+        },
+        Err(error) => TagEditResult {
+            file_path,
+            metadata: None,
+            error: Some(error),
+        },
+    }
+}
+
+/// Applies tag changes to many files at once, recording the values they replaced in an
+/// undo journal. The journal is saved before any file is written, so nothing is changed
+/// without a record of it. A failure on one file doesn't stop the others from being written.
+#[tauri::command]
+pub async fn set_metadata_batch(
+    app: AppHandle,
+    edits: Vec<TagEdit>,
+    preserve_date_modified: Option<bool>,
+) -> Result<TagBatchResult, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        write_metadata_batch(&app, edits, preserve_date_modified.unwrap_or(false))
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+fn write_metadata_batch(
+    app: &AppHandle,
+    edits: Vec<TagEdit>,
+    preserve_date_modified: bool,
+) -> Result<TagBatchResult, String> {
+    let date_created = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|e| e.to_string())?
+        .as_millis() as u64;
+    let mut journal = TagJournal {
+        id: format!(
+            "{}n{}",
+            date_created,
+            JOURNAL_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ),
+        date_created,
+        entries: Vec::new(),
+    };
+    let mut results = Vec::with_capacity(edits.len());
+
+    // Record the current values first, leaving out files that can't be read
+    let mut pending_edits = Vec::with_capacity(edits.len());
+    for edit in edits {
+        match read_primary_tag(Path::new(&edit.file_path)) {
+            Ok(tag) => {
+                journal.entries.push(TagJournalEntry {
+                    file_path: edit.file_path.clone(),
+                    previous_values: read_tag_values(&tag, &edit.changes),
+                    applied: false,
+                });
+                pending_edits.push(edit);
+            }
+            Err(error) => results.push(to_result(app, edit.file_path, Err(error))),
+        }
+    }
+    if journal.entries.is_empty() {
+        return Ok(TagBatchResult {
+            journal_id: None,
+            results,
+        });
+    }
+    save_journal(app, &journal)?;
+
+    for (index, edit) in pending_edits.into_iter().enumerate() {
+        let result = write_tag_changes(
+            Path::new(&edit.file_path),
+            &edit.changes,
+            preserve_date_modified,
+        );
+        journal.entries[index].applied = result.is_ok();
+        results.push(to_result(app, edit.file_path, result));
+    }
+
+    // Files that failed weren't changed, so there's nothing to undo for them
+    journal.entries.retain(|entry| entry.applied);
+    let journal_id = if journal.entries.is_empty() {
+        let _ = fs::remove_file(get_journal_path(app, &journal.id)?);
+        None
+    } else {
+        save_journal(app, &journal)?;
+        Some(journal.id)
+    };
+    Ok(TagBatchResult {
+        journal_id,
+        results,
+    })
+}
+
+/// Restores the tag values recorded in a journal. Files that fail to revert are kept
+/// in the journal so the undo can be retried; the journal is deleted once all succeed.
+/// Entries from an interrupted batch that weren't marked as applied are restored too,
+/// which does nothing if their file was never written.
+#[tauri::command]
+pub async fn undo_metadata_batch(
+    app: AppHandle,
+    journal_id: String,
+    preserve_date_modified: Option<bool>,
+) -> Result<Vec<TagEditResult>, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        undo_journal(&app, &journal_id, preserve_date_modified.unwrap_or(false))
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+fn undo_journal(
+    app: &AppHandle,
+    journal_id: &str,
+    preserve_date_modified: bool,
+) -> Result<Vec<TagEditResult>, String> {
+    let mut journal = load_journal(app, journal_id)?;
+    let mut failed_entries = Vec::new();
+    let mut results = Vec::with_capacity(journal.entries.len());
+
+    for entry in journal.entries {
+        let result = write_tag_changes(
+            Path::new(&entry.file_path),
+            &entry.previous_values,
+            preserve_date_modified,
+        );
+        if result.is_err() {
+            failed_entries.push(TagJournalEntry {
+                file_path: entry.file_path.clone(),
+                previous_values: entry.previous_values,
+                applied: entry.applied,
+            });
+        }
+        results.push(to_result(app, entry.file_path, result));
+    }
+
+    if failed_entries.is_empty() {
+        let _ = fs::remove_file(get_journal_path(app, journal_id)?);
+    } else {
+        journal.entries = failed_entries;
+        save_journal(app, &journal)?;
+    }
+    Ok(results)
+}
+
+#[tauri::command]
+pub fn get_metadata_journals(app: AppHandle) -> Result<Vec<TagJournalSummary>, String> {
+    let Ok(read_dir) = fs::read_dir(get_journal_dir(&app)?) else {
+        return Ok(Vec::new());
+    };
+    let mut journals: Vec<TagJournalSummary> = read_dir
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "json"))
+        .filter_map(|entry| {
+            let contents = fs::read(entry.path()).ok()?;
+            let journal: TagJournal = serde_json::from_slice(&contents).ok()?;
+            Some(TagJournalSummary {
+                id: journal.id,
+                date_created: journal.date_created,
+                file_count: journal.entries.len(),
+            })
+        })
+        .collect();
+    journals.sort_by_key(|journal| std::cmp::Reverse(journal.date_created));
+    Ok(journals)
+}
@@ -0,0 +1,199 @@
+use super::artwork::ArtworkInfo;
+use serde::Serialize;
+
+/// Incremented whenever a field is added, removed or changes meaning, so the frontend
+/// knows to read metadata it cached from an older version again.
+pub const METADATA_SCHEMA_VERSION: u32 = 3;
+
+/// Everything `get_metadata` reads from an audio file. Fields are `null` when the file
+/// doesn't have them, while lists are empty.
+#[derive(Serialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct TrackMetadata {
+    pub schema_version: u32,
+
+    // File
+    /// Duration in milliseconds.
+    pub duration: u64,
+    /// Modification time in milliseconds since the Unix epoch.
+    pub date_modified: u64,
+    /// Size in bytes.
+    pub file_size: u64,
+    /// For virtual tracks from a cue sheet, the file they're part of.
+    pub parent_file_path: Option<String>,
+    /// For virtual tracks from a cue sheet, where they start in the parent file in milliseconds.
+    pub start_offset: Option<u64>,
+    /// The tracks in the file's embedded cue sheet, which replace the file in the library.
+    /// Scans only split files by sidecar `.cue` files, so these are found here instead.
+    pub cue_track_uris: Vec<String>,
+
+    // Audio properties
+    /// Sample rate in hertz.
+    pub sample_rate: Option<u32>,
+    /// Bitrate of the audio stream in bits per second.
+    pub bit_rate: Option<u32>,
+    /// Bitrate of the whole file in bits per second, including tags and artwork.
+    pub overall_bit_rate: Option<u32>,
+    pub channels: Option<u8>,
+    pub bit_depth: Option<u8>,
+    /// For example `AAC` or `ALAC`, which can both be stored in an MP4 container.
+    pub codec: Option<String>,
+    pub container: Option<String>,
+    pub lossless: Option<bool>,
+    /// `cbr` or `vbr`, only known for MPEG audio.
+    pub bitrate_mode: Option<String>,
+    pub encoder: Option<String>,
+
+    // Basic tags
+    pub title: Option<String>,
+    pub artist: Vec<String>,
+    pub album: Option<String>,
+    pub album_artist: Option<String>,
+    pub genre: Vec<String>,
+    pub composer: Vec<String>,
+    pub comments: Vec<String>,
+    pub year: Option<u32>,
+    /// Release date in milliseconds since the Unix epoch, using the start of the year or
+    /// month when the tag doesn't have a full date.
+    pub date_released: Option<i64>,
+    pub track: Option<u32>,
+    pub track_total: Option<u32>,
+    pub disc: Option<u32>,
+    pub disc_total: Option<u32>,
+
+    // Extended tags
+    pub music_brainz_recording_id: Option<String>,
+    pub music_brainz_track_id: Option<String>,
+    pub music_brainz_release_id: Option<String>,
+    pub music_brainz_release_group_id: Option<String>,
+    pub music_brainz_work_id: Option<String>,
+    pub music_brainz_release_type: Option<String>,
+    pub music_brainz_artist_id: Vec<String>,
+    pub music_brainz_release_artist_id: Vec<String>,
+    pub title_sort: Option<String>,
+    pub artist_sort: Option<String>,
+    pub album_sort: Option<String>,
+    pub album_artist_sort: Option<String>,
+    pub composer_sort: Option<String>,
+    pub subtitle: Option<String>,
+    pub grouping: Option<String>,
+    pub bpm: Option<f64>,
+    pub initial_key: Option<String>,
+    pub isrc: Option<String>,
+    pub barcode: Option<String>,
+    pub label: Option<String>,
+    pub catalog_number: Option<String>,
+    pub work: Option<String>,
+    pub movement: Option<String>,
+    pub movement_number: Option<u32>,
+    pub movement_total: Option<u32>,
+    pub original_release_date: Option<String>,
+    pub release_country: Option<String>,
+    pub copyright: Option<String>,
+    pub conductor: Vec<String>,
+    pub lyricist: Vec<String>,
+    pub arranger: Vec<String>,
+    pub producer: Vec<String>,
+    pub remixer: Vec<String>,
+    pub mood: Vec<String>,
+    pub compilation: Option<bool>,
+    /// `explicit`, `clean` or `none`.
+    pub advisory: Option<String>,
+
+    // Loudness
+    /// ReplayGain gains are in dB and peaks are linear, where 1.0 is full scale.
+    pub replay_gain_track_gain: Option<f64>,
+    pub replay_gain_album_gain: Option<f64>,
+    pub replay_gain_track_peak: Option<f64>,
+    pub replay_gain_album_peak: Option<f64>,
+    /// Opus R128 gains in dB, relative to -23 LUFS instead of ReplayGain's -18.
+    pub r128_track_gain: Option<f64>,
+    pub r128_album_gain: Option<f64>,
+
+    // Artwork
+    /// The hash of the front cover, or the first picture if there isn't one.
+    pub artwork_uri: Option<String>,
+    pub artwork: Vec<ArtworkInfo>,
+}
+
+/// Parses the year from a date such as `2004` or `2004-05-12`.
+pub fn parse_year(value: &str) -> Option<u32> {
+    value
+        .trim()
+        .get(..4)
+        .and_then(|year| year.parse().ok())
+        .filter(|year| *year > 0)
+}
+
+/// Days since the Unix epoch for a date in the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// Parses an ID3v2.4 style timestamp, such as `2004`, `2004-05` or `2004-05-12T10:00`,
+/// into milliseconds since the Unix epoch. Any time of day is ignored.
+pub fn parse_date(value: &str) -> Option<i64> {
+    let date = value.trim().split(['T', ' ']).next()?;
+    let mut parts = date.split('-');
+    let year: i64 = parts.next().filter(|year| year.len() == 4)?.parse().ok()?;
+    let month: i64 = match parts.next() {
+        Some(month) => month
+            .parse()
+            .ok()
+            .filter(|month| (1..=12).contains(month))?,
+        None => 1,
+    };
+    let day: i64 = match parts.next() {
+        Some(day) => day.parse().ok().filter(|day| (1..=31).contains(day))?,
+        None => 1,
+    };
+    Some(days_from_civil(year, month, day) * 86_400_000)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: i64 = 86_400_000;
+
+    #[test]
+    fn counts_days_from_the_epoch() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(1969, 12, 31), -1);
+        assert_eq!(days_from_civil(2000, 2, 29), 11_016);
+        assert_eq!(days_from_civil(2000, 3, 1), 11_017);
+        assert_eq!(days_from_civil(1600, 1, 1), -135_140);
+    }
+
+    #[test]
+    fn parses_full_and_partial_dates() {
+        assert_eq!(parse_date("1970"), Some(0));
+        assert_eq!(parse_date("2004-05"), Some(12_539 * DAY));
+        assert_eq!(parse_date("2004-05-12"), Some(12_550 * DAY));
+        assert_eq!(parse_date("2004-05-12T10:00"), Some(12_550 * DAY));
+        assert_eq!(parse_date(" 2004-05-12 10:00:00 "), Some(12_550 * DAY));
+    }
+
+    #[test]
+    fn rejects_invalid_dates() {
+        assert_eq!(parse_date(""), None);
+        assert_eq!(parse_date("04"), None);
+        assert_eq!(parse_date("2004-13"), None);
+        assert_eq!(parse_date("2004-00"), None);
+        assert_eq!(parse_date("2004-05-32"), None);
+        assert_eq!(parse_date("May 2004"), None);
+    }
+
+    #[test]
+    fn parses_years() {
+        assert_eq!(parse_year("2004"), Some(2004));
+        assert_eq!(parse_year("2004-05-12"), Some(2004));
+        assert_eq!(parse_year("0000"), None);
+        assert_eq!(parse_year("99"), None);
+    }
+}
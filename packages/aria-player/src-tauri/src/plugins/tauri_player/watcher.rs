@@ -0,0 +1,309 @@
+use super::cue_sheet::get_referenced_files;
+use super::scan_index::load_scan_index;
+use super::scanner::{
+    get_track_uris, is_temp_file, walk_audio_files_with_filter, LibraryFilter, ScanOptions,
+};
+use notify_debouncer_full::notify::event::{ModifyKind, RenameMode};
+use notify_debouncer_full::notify::{EventKind, RecommendedWatcher, RecursiveMode};
+use notify_debouncer_full::{
+    new_debouncer, DebounceEventResult, DebouncedEvent, Debouncer, RecommendedCache,
+};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, State};
+
+const DEBOUNCE_TIMEOUT: Duration = Duration::from_millis(1000);
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryFileEvent {
+    pub path: String,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryFileMovedEvent {
+    pub from: String,
+    pub to: String,
+}
+
+pub struct LibraryWatcher {
+    debouncer: Option<Debouncer<RecommendedWatcher, RecommendedCache>>,
+    roots: Arc<Mutex<HashSet<PathBuf>>>,
+}
+
+impl LibraryWatcher {
+    pub fn new() -> Self {
+        Self {
+            debouncer: None,
+            roots: Arc::new(Mutex::new(HashSet::new())),
+        }
+    }
+
+    fn ensure_debouncer_running(
+        &mut self,
+        app: &AppHandle,
+    ) -> Result<&mut Debouncer<RecommendedWatcher, RecommendedCache>, String> {
+        if self.debouncer.is_none() {
+            let app = app.clone();
+            let roots = self.roots.clone();
+            let debouncer = new_debouncer(
+                DEBOUNCE_TIMEOUT,
+                None,
+                move |result: DebounceEventResult| match result {
+                    Ok(events) => {
+                        let options = ScanOptions::from_config(&app);
+                        for event in events {
+                            handle_event(&app, &roots, &options, &event);
+                        }
+                    }
+                    Err(errors) => {
+                        for error in errors {
+                            eprintln!("Library watcher error: {}", error);
+                        }
+                    }
+                },
+            )
+            .map_err(|e| e.to_string())?;
+            self.debouncer = Some(debouncer);
+        }
+        Ok(self.debouncer.as_mut().unwrap())
+    }
+
+    fn watch(&mut self, app: &AppHandle, directory_path: &Path) -> Result<(), String> {
+        if self.roots.lock().unwrap().contains(directory_path) {
+            return Ok(());
+        }
+        self.ensure_debouncer_running(app)?
+            .watch(directory_path, RecursiveMode::Recursive)
+            .map_err(|e| e.to_string())?;
+        self.roots
+            .lock()
+            .unwrap()
+            .insert(directory_path.to_path_buf());
+        Ok(())
+    }
+
+    fn unwatch(&mut self, directory_path: &Path) -> Result<(), String> {
+        if !self.roots.lock().unwrap().remove(directory_path) {
+            return Ok(());
+        }
+        if let Some(debouncer) = self.debouncer.as_mut() {
+            debouncer
+                .unwatch(directory_path)
+                .map_err(|e| e.to_string())?;
+        }
+        if self.roots.lock().unwrap().is_empty() {
+            if let Some(debouncer) = self.debouncer.take() {
+                debouncer.stop_nonblocking();
+            }
+        }
+        Ok(())
+    }
+}
+
+fn emit_file_event(app: &AppHandle, event: &str, path: &Path) {
+    let _ = app.emit_to(
+        "main",
+        event,
+        LibraryFileEvent {
+            path: path.display().to_string(),
+        },
+    );
+}
+
+fn emit_file_moved(app: &AppHandle, from: &Path, to: &Path) {
+    let _ = app.emit_to(
+        "main",
+        "library:file_moved",
+        LibraryFileMovedEvent {
+            from: from.display().to_string(),
+            to: to.display().to_string(),
+        },
+    );
+}
+
+/// Emits an event for an audio file, or for each of its tracks if it has a cue sheet.
+fn emit_track_events(app: &AppHandle, event: &str, options: &ScanOptions, path: &Path) {
+    for uri in get_track_uris(path, options, true) {
+        emit_file_event(app, event, &uri);
+    }
+}
+
+fn is_cue_file(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("cue"))
+}
+
+/// A cue sheet decides how its audio files are split into tracks, so a new or edited
+/// sheet counts as a change to each file it has tracks in.
+fn report_cue_sheet_changed(
+    app: &AppHandle,
+    options: &ScanOptions,
+    filter: &LibraryFilter,
+    cue_path: &Path,
+) {
+    if !options.split_cue_sheets {
+        return;
+    }
+    for path in get_referenced_files(cue_path) {
+        if !filter.is_excluded_path(&path) && options.is_audio_file(&path) {
+            emit_track_events(app, "library:file_changed", options, &path);
+        }
+    }
+}
+
+fn report_changed(app: &AppHandle, options: &ScanOptions, filter: &LibraryFilter, path: &Path) {
+    if !path.is_file() || filter.is_excluded_path(path) {
+        return;
+    }
+    if is_cue_file(path) {
+        report_cue_sheet_changed(app, options, filter, path);
+    } else if options.is_audio_file(path) {
+        emit_track_events(app, "library:file_changed", options, path);
+    }
+}
+
+fn report_added(app: &AppHandle, options: &ScanOptions, filter: &LibraryFilter, path: &Path) {
+    if filter.is_excluded_path(path) {
+        return;
+    }
+    if path.is_dir() {
+        walk_audio_files_with_filter(path, options, filter, &mut |file| {
+            emit_file_event(app, "library:file_added", &file);
+        });
+    } else if is_cue_file(path) {
+        report_cue_sheet_changed(app, options, filter, path);
+    } else if options.is_audio_file(path) {
+        emit_track_events(app, "library:file_added", options, path);
+    }
+}
+
+fn report_removed(app: &AppHandle, options: &ScanOptions, root: &Path, path: &Path) {
+    if options.has_audio_extension(path) {
+        // A sidecar cue sheet can still be read to find the tracks the file was split into
+        emit_track_events(app, "library:file_removed", options, path);
+        return;
+    }
+    // A removed directory can no longer be listed, so fall back to the files the last scan found inside it
+    if let Ok(index) = load_scan_index(app, root) {
+        for file in index.entries.keys() {
+            if Path::new(file).starts_with(path) {
+                emit_file_event(app, "library:file_removed", Path::new(file));
+            }
+        }
+    }
+}
+
+fn report_moved(
+    app: &AppHandle,
+    options: &ScanOptions,
+    filter: &LibraryFilter,
+    from: &Path,
+    to: &Path,
+) {
+    if filter.is_excluded_path(to) {
+        return;
+    }
+    if is_temp_file(from) {
+        // Tag edits are written to a temporary copy that then replaces the original file
+        report_changed(app, options, filter, to);
+    } else if to.is_dir() {
+        walk_audio_files_with_filter(to, options, filter, &mut |file| {
+            if let Ok(relative_path) = file.strip_prefix(to) {
+                emit_file_moved(app, &from.join(relative_path), &file);
+            }
+        });
+    } else if options.has_audio_extension(from) && options.is_audio_file(to) {
+        // Each track of a split file moves with it, keeping its number
+        let (from, to_prefix) = (from.display().to_string(), to.display().to_string());
+        for uri in get_track_uris(to, options, true) {
+            let uri = uri.display().to_string();
+            if let Some(track) = uri.strip_prefix(&to_prefix) {
+                emit_file_moved(
+                    app,
+                    Path::new(&format!("{}{}", from, track)),
+                    Path::new(&uri),
+                );
+            }
+        }
+    } else if is_cue_file(to) {
+        report_cue_sheet_changed(app, options, filter, to);
+    } else if options.is_audio_file(to) {
+        emit_track_events(app, "library:file_added", options, to);
+    } else if options.has_audio_extension(from) {
+        emit_file_event(app, "library:file_removed", from);
+    }
+}
+
+fn handle_event(
+    app: &AppHandle,
+    roots: &Mutex<HashSet<PathBuf>>,
+    options: &ScanOptions,
+    event: &DebouncedEvent,
+) {
+    let Some(root) = event.paths.last().and_then(|path| {
+        roots
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|root| path.starts_with(root))
+            .cloned()
+    }) else {
+        return;
+    };
+    let filter = options.filter_for_root(&root);
+    match event.kind {
+        EventKind::Create(_) => {
+            for path in &event.paths {
+                report_added(app, options, &filter, path);
+            }
+        }
+        EventKind::Remove(_) => {
+            for path in &event.paths {
+                report_removed(app, options, &root, path);
+            }
+        }
+        EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => {
+            if let [from, to] = event.paths.as_slice() {
+                report_moved(app, options, &filter, from, to);
+            }
+        }
+        EventKind::Modify(ModifyKind::Name(_)) => {
+            // Renames that couldn't be paired up are either moves in or out of the watched folders
+            for path in &event.paths {
+                if path.exists() {
+                    report_added(app, options, &filter, path);
+                } else {
+                    report_removed(app, options, &root, path);
+                }
+            }
+        }
+        EventKind::Modify(ModifyKind::Metadata(_)) => {}
+        EventKind::Modify(_) => {
+            for path in &event.paths {
+                report_changed(app, options, &filter, path);
+            }
+        }
+        _ => {}
+    }
+}
+
+type WatcherState<'a> = State<'a, Mutex<LibraryWatcher>>;
+
+#[tauri::command]
+pub fn watch_library_folder(
+    app: AppHandle,
+    state: WatcherState,
+    directory_path: &Path,
+) -> Result<(), String> {
+    state.lock().unwrap().watch(&app, directory_path)
+}
+
+#[tauri::command]
+pub fn unwatch_library_folder(state: WatcherState, directory_path: &Path) -> Result<(), String> {
+    state.lock().unwrap().unwatch(directory_path)
+}
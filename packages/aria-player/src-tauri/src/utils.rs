@@ -1,3 +1,4 @@
+use crate::plugins::tauri_player::playlist::{read_playlist, Playlist, PlaylistFormat};
 use std::path::PathBuf;
 use tauri::menu::MenuItemKind;
 use tauri::AppHandle;
@@ -51,6 +52,20 @@ pub fn check_for_files(app: &AppHandle, args: Vec<String>) {
             .unwrap_or_else(|| PathBuf::from(&maybe_file));
         files.push(path);
     }
+    // Playlists are imported here, so the frontend gets their entries instead of the file
+    let (playlist_files, files): (Vec<PathBuf>, Vec<PathBuf>) = files
+        .into_iter()
+        .partition(|file| PlaylistFormat::from_path(file).is_some());
+    let playlists: Vec<Playlist> = playlist_files
+        .iter()
+        .filter_map(|file| read_playlist(file).ok())
+        .collect();
+    if !playlists.is_empty() {
+        let _ = app.emit("opened_playlists", playlists);
+    }
+    if files.is_empty() {
+        return;
+    }
     let asset_protocol_scope = app.asset_protocol_scope();
     for file in &files {
         let _ = asset_protocol_scope.allow_file(file);
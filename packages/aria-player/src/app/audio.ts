@@ -1,8 +1,26 @@
+/**
+ * The part of a file to play, in milliseconds, for tracks that share a file
+ * with others. Without an end, the section plays until the end of the file.
+ */
+export type AudioSection = {
+  start: number;
+  end?: number;
+};
+
 type AudioBufferInfo = {
   buffer: AudioBuffer;
   fileUrl: string;
+  /** The file the buffer was decoded from, which tracks in sections share. */
+  file: string;
+  section?: AudioSection;
 };
 
+function getSectionDuration(bufferInfo: AudioBufferInfo) {
+  const start = (bufferInfo.section?.start ?? 0) / 1000;
+  const end = (bufferInfo.section?.end ?? Infinity) / 1000;
+  return Math.max(0, Math.min(end, bufferInfo.buffer.duration) - start);
+}
+
 export function createWebAudioBackend(props: {
   onFinishedPlayback: () => void;
 }) {
@@ -24,6 +42,12 @@ export function createWebAudioBackend(props: {
     return await audioContext.decodeAudioData(arrayBuffer);
   }
 
+  function getDecodedBuffer(file: string) {
+    if (primaryBufferInfo?.file === file) return primaryBufferInfo.buffer;
+    if (secondaryBufferInfo?.file === file) return secondaryBufferInfo.buffer;
+    return null;
+  }
+
   function playPrimaryBuffer(
     startPosition: number,
     volume: number,
@@ -40,7 +64,12 @@ export function createWebAudioBackend(props: {
     sourceNode.buffer = primaryBufferInfo.buffer;
     sourceNode.connect(gainNode);
     gainNode.connect(audioContext.destination);
-    sourceNode.start(0, startPosition);
+    const sectionStart = (primaryBufferInfo.section?.start ?? 0) / 1000;
+    sourceNode.start(
+      0,
+      sectionStart + startPosition,
+      Math.max(0, getSectionDuration(primaryBufferInfo) - startPosition)
+    );
     sourceNode.onended = async () => {
       if (secondaryBufferInfo) {
         primaryBufferInfo = secondaryBufferInfo;
@@ -55,7 +84,8 @@ export function createWebAudioBackend(props: {
     async loadPrimaryAudioFile(
       fileUrl: string,
       file: string,
-      volume: number
+      volume: number,
+      section?: AudioSection
     ): Promise<number | null> {
       primaryAbortController?.abort();
       primaryAbortController = new AbortController();
@@ -65,21 +95,30 @@ export function createWebAudioBackend(props: {
         return null;
       }
       if (secondaryBufferInfo?.fileUrl === fileUrl) {
-        const newDuration = secondaryBufferInfo.buffer.duration * 1000;
+        const newDuration = getSectionDuration(secondaryBufferInfo) * 1000;
         primaryBufferInfo = secondaryBufferInfo;
         secondaryBufferInfo = null;
         playPrimaryBuffer(0, volume, true);
         return newDuration;
       }
+      // Another section of a file that's already decoded
+      const decodedBuffer = getDecodedBuffer(file);
+      if (decodedBuffer) {
+        primaryBufferInfo = { buffer: decodedBuffer, fileUrl, file, section };
+        playPrimaryBuffer(0, volume, true);
+        return getSectionDuration(primaryBufferInfo) * 1000;
+      }
       await audioContext.suspend();
       try {
         const buffer = await loadAudioBuffer(file, signal);
         primaryBufferInfo = {
           buffer,
           fileUrl,
+          file,
+          section,
         };
         playPrimaryBuffer(0, volume, true);
-        return primaryBufferInfo.buffer.duration * 1000;
+        return getSectionDuration(primaryBufferInfo) * 1000;
       } catch (error) {
         if (error instanceof DOMException && error.name === "AbortError") {
           // Cancelled load
@@ -90,15 +129,26 @@ export function createWebAudioBackend(props: {
       }
     },
 
-    async loadSecondaryAudioFile(fileUrl: string, file: string) {
+    async loadSecondaryAudioFile(
+      fileUrl: string,
+      file: string,
+      section?: AudioSection
+    ) {
       secondaryAbortController?.abort();
       secondaryAbortController = new AbortController();
       const signal = secondaryAbortController.signal;
+      const decodedBuffer = getDecodedBuffer(file);
+      if (decodedBuffer) {
+        secondaryBufferInfo = { buffer: decodedBuffer, fileUrl, file, section };
+        return;
+      }
       try {
         const buffer = await loadAudioBuffer(file, signal);
         secondaryBufferInfo = {
           buffer,
           fileUrl,
+          file,
+          section,
         };
       } catch (error) {
         if (error instanceof DOMException && error.name === "AbortError") {
@@ -22,6 +22,17 @@ import { store } from "../app/store";
 import { useLocation } from "react-router-dom";
 import menus from "../../shared/menus.json";
 import { installPluginsFromFiles } from "../features/plugins/pluginsSlice";
+import { nanoid } from "@reduxjs/toolkit";
+import {
+  addTracksToPlaylist,
+  createPlaylistItem,
+} from "../features/playlists/playlistsSlice";
+import { selectTrackById } from "../features/tracks/tracksSlice";
+
+type OpenedPlaylist = {
+  name: string;
+  entries: { location: string; isUrl: boolean }[];
+};
 
 const appWindow = isTauri() ? getCurrentWebviewWindow() : null;
 
@@ -193,6 +204,40 @@ export function PlatformProvider({ children }: { children: ReactNode }) {
     };
   }, [dispatch]);
 
+  useEffect(() => {
+    const unlisten = appWindow?.listen<OpenedPlaylist[]>(
+      "opened_playlists",
+      ({ payload: playlists }) => {
+        // Only entries for tracks already in the library can be added
+        for (const playlist of playlists) {
+          const playlistId = nanoid();
+          const trackIds = playlist.entries
+            .filter((entry) => !entry.isUrl)
+            .map((entry) => "tauri-player:" + entry.location)
+            .filter((trackId) => selectTrackById(store.getState(), trackId));
+          dispatch(
+            createPlaylistItem({
+              newData: { id: playlistId, name: playlist.name },
+            })
+          );
+          dispatch(
+            addTracksToPlaylist({
+              playlistId,
+              newTracks: trackIds.map((trackId) => ({
+                itemId: nanoid(),
+                trackId,
+              })),
+            })
+          );
+        }
+      }
+    );
+
+    return () => {
+      unlisten?.then((fn) => Promise.resolve(fn()).catch(() => {}));
+    };
+  }, [dispatch]);
+
   useEffect(() => {
     if (isTauri()) {
       invoke("update_menu_state", { menuState });
@@ -0,0 +1,203 @@
+import styles from "./LibraryConfig.module.css";
+import { invoke } from "@tauri-apps/api/core";
+import { i18n } from "i18next";
+import { useEffect, useRef, useState } from "react";
+import { useTranslation } from "react-i18next";
+
+type EqBand = {
+  kind: "peaking" | "lowShelf" | "highShelf";
+  frequency: number;
+  gain: number;
+  q: number;
+};
+
+type EqPreset = {
+  preamp: number;
+  bands: EqBand[];
+};
+
+type DspSettings = {
+  eqEnabled: boolean;
+  preamp: number;
+  bands: EqBand[];
+  limiter: boolean;
+  balance: number;
+  mono: boolean;
+};
+
+/** How long to wait after the last change before saving the settings. */
+const SAVE_DEBOUNCE_MS = 500;
+
+function formatFrequency(frequency: number) {
+  return frequency >= 1000 ? `${frequency / 1000}k` : `${frequency}`;
+}
+
+/**
+ * Edits the native playback engine's DSP chain, which is applied to playback
+ * as soon as anything changes.
+ */
+export function Equalizer(props: { i18n: i18n }) {
+  const { t } = useTranslation("tauri-player", { i18n: props.i18n });
+  const [settings, setSettings] = useState<DspSettings | null>(null);
+  const [presets, setPresets] = useState<Record<string, EqPreset>>({});
+  const [presetName, setPresetName] = useState("");
+  const saveTimer = useRef<ReturnType<typeof setTimeout>>();
+
+  useEffect(() => {
+    invoke<DspSettings>("get_dsp_settings").then(setSettings);
+    invoke<Record<string, EqPreset>>("get_eq_presets").then(setPresets);
+  }, []);
+
+  if (!settings) {
+    return null;
+  }
+
+  // Every change is heard straight away, but dragging a slider is only saved
+  // once it stops. The timer isn't cleared on unmount, so the last change is
+  // still saved after closing the settings
+  const updateSettings = (changes: Partial<DspSettings>) => {
+    const updated = { ...settings, ...changes };
+    setSettings(updated);
+    invoke("set_dsp_settings", { settings: updated, save: false });
+    clearTimeout(saveTimer.current);
+    saveTimer.current = setTimeout(() => {
+      invoke("set_dsp_settings", { settings: updated });
+    }, SAVE_DEBOUNCE_MS);
+  };
+
+  const updateBand = (index: number, gain: number) => {
+    updateSettings({
+      bands: settings.bands.map((band, i) =>
+        i === index ? { ...band, gain } : band
+      ),
+    });
+  };
+
+  const savePreset = async () => {
+    const name = presetName.trim();
+    if (!name) {
+      return;
+    }
+    const preset = { preamp: settings.preamp, bands: settings.bands };
+    await invoke("save_eq_preset", { name, preset });
+    setPresets({ ...presets, [name]: preset });
+    setPresetName("");
+  };
+
+  const deletePreset = async (name: string) => {
+    await invoke("delete_eq_preset", { name });
+    const remaining = { ...presets };
+    delete remaining[name];
+    setPresets(remaining);
+  };
+
+  return (
+    <>
+      <h4 className="settings-heading">{t("config.equalizer.title")}</h4>
+      <div className="settings-checkbox-container">
+        <input
+          type="checkbox"
+          className="settings-checkbox"
+          checked={settings.eqEnabled}
+          onChange={(e) => updateSettings({ eqEnabled: e.target.checked })}
+        />{" "}
+        {t("config.equalizer.enabled")}
+      </div>
+      <select
+        className="settings-select"
+        value=""
+        onChange={(e) => {
+          const preset = presets[e.target.value];
+          if (preset) {
+            updateSettings({ ...preset, eqEnabled: true });
+          }
+        }}
+      >
+        <option value="" disabled>
+          {t("config.equalizer.loadPreset")}
+        </option>
+        {Object.keys(presets).map((name) => (
+          <option key={name} value={name}>
+            {name}
+          </option>
+        ))}
+      </select>
+      <div className={styles.equalizer}>
+        <label className={styles.band}>
+          <input
+            type="range"
+            min={-12}
+            max={12}
+            step={0.5}
+            value={settings.preamp}
+            onChange={(e) => updateSettings({ preamp: Number(e.target.value) })}
+          />
+          <span>{t("config.equalizer.preamp")}</span>
+          <span>{settings.preamp} dB</span>
+        </label>
+        {settings.bands.map((band, index) => (
+          <label key={index} className={styles.band}>
+            <input
+              type="range"
+              min={-12}
+              max={12}
+              step={0.5}
+              value={band.gain}
+              onChange={(e) => updateBand(index, Number(e.target.value))}
+            />
+            <span>{formatFrequency(band.frequency)}</span>
+            <span>{band.gain} dB</span>
+          </label>
+        ))}
+      </div>
+      <div className={styles.presetActions}>
+        <input
+          type="text"
+          value={presetName}
+          placeholder={t("config.equalizer.presetName")}
+          onChange={(e) => setPresetName(e.target.value)}
+        />
+        <button className="settings-button" onClick={savePreset}>
+          {t("config.equalizer.savePreset")}
+        </button>
+        {presets[presetName.trim()] && (
+          <button
+            className="settings-button"
+            onClick={() => deletePreset(presetName.trim())}
+          >
+            {t("config.equalizer.deletePreset")}
+          </button>
+        )}
+      </div>
+      <div className="settings-checkbox-container">
+        <input
+          type="checkbox"
+          className="settings-checkbox"
+          checked={settings.limiter}
+          onChange={(e) => updateSettings({ limiter: e.target.checked })}
+        />{" "}
+        {t("config.equalizer.limiter")}
+      </div>
+      <div className="settings-checkbox-container">
+        <input
+          type="checkbox"
+          className="settings-checkbox"
+          checked={settings.mono}
+          onChange={(e) => updateSettings({ mono: e.target.checked })}
+        />{" "}
+        {t("config.equalizer.mono")}
+      </div>
+      <label className={styles.balance}>
+        {t("config.equalizer.balance")}
+        <input
+          type="range"
+          min={-1}
+          max={1}
+          step={0.05}
+          value={settings.balance}
+          onChange={(e) => updateSettings({ balance: Number(e.target.value) })}
+        />
+      </label>
+    </>
+  );
+}
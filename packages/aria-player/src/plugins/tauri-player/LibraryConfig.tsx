@@ -1,4 +1,6 @@
 import styles from "./LibraryConfig.module.css";
+import { Equalizer } from "./Equalizer";
+import { Normalization } from "./Normalization";
 import RemoveIcon from "../../assets/trash-can-solid.svg?react";
 import { i18n } from "i18next";
 import { useTranslation } from "react-i18next";
@@ -11,7 +13,10 @@ export function LibraryConfig(props: {
   updateData: (data: object) => void;
   i18n: i18n;
 }) {
-  const tauriData = props.data as { showAttribution?: boolean };
+  const tauriData = props.data as {
+    showAttribution?: boolean;
+    nativePlayback?: boolean;
+  };
   const { t } = useTranslation("tauri-player", { i18n: props.i18n });
 
   return (
@@ -71,6 +76,23 @@ export function LibraryConfig(props: {
         />{" "}
         {t("config.showAttribution")}
       </div>
+      <div className="settings-checkbox-container">
+        <input
+          type="checkbox"
+          className="settings-checkbox"
+          checked={tauriData.nativePlayback ?? false}
+          onChange={(e) =>
+            props.updateData({ nativePlayback: e.target.checked })
+          }
+        />{" "}
+        {t("config.nativePlayback")}
+      </div>
+      {tauriData.nativePlayback && (
+        <>
+          <Normalization i18n={props.i18n} />
+          <Equalizer i18n={props.i18n} />
+        </>
+      )}
     </>
   );
 }
@@ -0,0 +1,109 @@
+import { invoke } from "@tauri-apps/api/core";
+import { i18n } from "i18next";
+import { useEffect, useState } from "react";
+import { useTranslation } from "react-i18next";
+
+type NormalizationSettings = {
+  mode: "off" | "track" | "album";
+  targetLoudness: number;
+  preGain: number;
+  preventClipping: boolean;
+};
+
+/**
+ * Edits volume normalization for the native playback engine, which uses the
+ * ReplayGain or R128 gains from each track's tags.
+ */
+export function Normalization(props: { i18n: i18n }) {
+  const { t } = useTranslation("tauri-player", { i18n: props.i18n });
+  const [settings, setSettings] = useState<NormalizationSettings | null>(
+    null
+  );
+
+  useEffect(() => {
+    invoke<NormalizationSettings>("get_normalization_settings").then(
+      setSettings
+    );
+  }, []);
+
+  if (!settings) {
+    return null;
+  }
+
+  const updateSettings = (changes: Partial<NormalizationSettings>) => {
+    const updated = { ...settings, ...changes };
+    setSettings(updated);
+    invoke("set_normalization_settings", { settings: updated });
+  };
+
+  // Number inputs are empty while their value is being replaced, which isn't
+  // saved, and the engine clamps the levels to the same range as the inputs
+  const updateLevel = (
+    key: "targetLoudness" | "preGain",
+    input: HTMLInputElement
+  ) => {
+    const value = input.valueAsNumber;
+    if (input.value.trim() === "" || Number.isNaN(value)) {
+      return;
+    }
+    const clamped = Math.min(
+      Math.max(value, Number(input.min)),
+      Number(input.max)
+    );
+    updateSettings(
+      key === "preGain" ? { preGain: clamped } : { targetLoudness: clamped }
+    );
+  };
+
+  return (
+    <>
+      <h4 className="settings-heading">{t("config.normalization.title")}</h4>
+      <select
+        className="settings-select"
+        value={settings.mode}
+        onChange={(e) =>
+          updateSettings({
+            mode: e.target.value as NormalizationSettings["mode"],
+          })
+        }
+      >
+        <option value="off">{t("config.normalization.off")}</option>
+        <option value="track">{t("config.normalization.track")}</option>
+        <option value="album">{t("config.normalization.album")}</option>
+      </select>
+      <div className="settings-checkbox-container">
+        {t("config.normalization.targetLoudness")}{" "}
+        <input
+          type="number"
+          min={-30}
+          max={-5}
+          step={1}
+          value={settings.targetLoudness}
+          onChange={(e) => updateLevel("targetLoudness", e.target)}
+        />
+      </div>
+      <div className="settings-checkbox-container">
+        {t("config.normalization.preGain")}{" "}
+        <input
+          type="number"
+          min={-15}
+          max={15}
+          step={0.5}
+          value={settings.preGain}
+          onChange={(e) => updateLevel("preGain", e.target)}
+        />
+      </div>
+      <div className="settings-checkbox-container">
+        <input
+          type="checkbox"
+          className="settings-checkbox"
+          checked={settings.preventClipping}
+          onChange={(e) =>
+            updateSettings({ preventClipping: e.target.checked })
+          }
+        />{" "}
+        {t("config.normalization.preventClipping")}
+      </div>
+    </>
+  );
+}
@@ -0,0 +1,104 @@
+import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
+
+type PlaybackStatus = {
+  state: "stopped" | "loading" | "playing" | "paused" | "ended";
+  uri: string | null;
+  position: number;
+  duration: number | null;
+};
+
+type TrackChange = {
+  previousUri: string;
+  uri: string;
+};
+
+type PlaybackError = {
+  uri: string | null;
+  message: string;
+};
+
+/**
+ * Plays tracks with the playback engine in the Tauri backend instead of the
+ * webview, using the same URIs as the library.
+ */
+export function createNativeAudioBackend(props: {
+  onFinishedPlayback: () => void;
+  onUnsupportedTrack: (uri: string) => void;
+}) {
+  let currentUri: string | null = null;
+
+  const unlistenStatus = listen<PlaybackStatus>(
+    "playback_status",
+    ({ payload }) => {
+      if (payload.state === "ended" && payload.uri === currentUri) {
+        props.onFinishedPlayback();
+      }
+    }
+  );
+
+  // Playback continues into the next track by itself, and the engine ignores
+  // loading it again once the player moves on to it
+  const unlistenTrackChange = listen<TrackChange>(
+    "playback_track_changed",
+    ({ payload }) => {
+      if (payload.previousUri === currentUri) {
+        currentUri = payload.uri;
+        props.onFinishedPlayback();
+      }
+    }
+  );
+
+  // Formats the engine can't decode are handed back to be played some other
+  // way, while tracks that fail for other reasons are skipped like tracks that
+  // finished. Errors for the next track are ignored, since it's opened again
+  // once the player gets to it
+  const unlistenError = listen<PlaybackError>(
+    "playback_error",
+    ({ payload }) => {
+      if (payload.uri == null || payload.uri !== currentUri) {
+        console.error(`Error playing ${payload.uri}:`, payload.message);
+      } else if (payload.message === "unsupported_codec") {
+        props.onUnsupportedTrack(payload.uri);
+      } else {
+        console.error(`Error playing ${payload.uri}:`, payload.message);
+        props.onFinishedPlayback();
+      }
+    }
+  );
+
+  return {
+    async loadPrimaryAudioFile(uri: string, volume: number) {
+      currentUri = uri;
+      await invoke("set_playback_volume", { volume });
+      await invoke("load_playback_track", { uri });
+    },
+
+    setNextTrack(uri: string | null) {
+      invoke("set_next_playback_track", { uri });
+    },
+
+    pause() {
+      invoke("pause_playback");
+    },
+
+    resume() {
+      invoke("resume_playback");
+    },
+
+    setVolume(volume: number) {
+      invoke("set_playback_volume", { volume });
+    },
+
+    setTime(position: number) {
+      invoke("seek_playback", { position: Math.round(position) });
+    },
+
+    dispose() {
+      invoke("stop_playback");
+      unlistenStatus.then((fn) => fn());
+      unlistenTrackChange.then((fn) => fn());
+      unlistenError.then((fn) => fn());
+    },
+  };
+}
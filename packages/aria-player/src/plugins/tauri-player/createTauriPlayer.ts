@@ -8,11 +8,155 @@ import en_us from "./locales/en_us/translation.json";
 import QuickStart from "./QuickStart";
 import { LibraryConfig } from "./LibraryConfig";
 import Attribution from "./Attribution";
-import { createWebAudioBackend } from "../../app/audio";
+import { AudioSection, createWebAudioBackend } from "../../app/audio";
+import { createNativeAudioBackend } from "./createNativeAudioBackend";
+
+type ScanError = {
+  path: string;
+  kind: "permissionDenied" | "brokenSymlink" | "unreadable" | "timeout";
+  message: string;
+};
+
+type AudioFilesScan = {
+  files: string[];
+  errors: ScanError[];
+};
+
+/**
+ * The version of the metadata returned by `get_metadata`. Tracks loaded with an
+ * older version are read again so they pick up new fields.
+ */
+const METADATA_SCHEMA_VERSION = 3;
+
+type ArtworkInfo = {
+  hash: string;
+  pictureType: string;
+  mimeType: string | null;
+};
+
+/**
+ * Metadata as returned by `get_metadata`. Fields the file doesn't have are
+ * null.
+ */
+type FileMetadata = {
+  schemaVersion: number;
+  duration: number;
+  dateModified: number;
+  fileSize: number;
+  parentFilePath: string | null;
+  startOffset: number | null;
+  cueTrackUris: string[];
+  sampleRate: number | null;
+  bitRate: number | null;
+  overallBitRate: number | null;
+  channels: number | null;
+  bitDepth: number | null;
+  codec: string | null;
+  container: string | null;
+  lossless: boolean | null;
+  bitrateMode: "cbr" | "vbr" | null;
+  encoder: string | null;
+  title: string | null;
+  artist: string[];
+  album: string | null;
+  albumArtist: string | null;
+  genre: string[];
+  composer: string[];
+  comments: string[];
+  year: number | null;
+  dateReleased: number | null;
+  track: number | null;
+  trackTotal: number | null;
+  disc: number | null;
+  discTotal: number | null;
+  musicBrainzRecordingId: string | null;
+  musicBrainzTrackId: string | null;
+  musicBrainzReleaseId: string | null;
+  musicBrainzReleaseGroupId: string | null;
+  musicBrainzWorkId: string | null;
+  musicBrainzReleaseType: string | null;
+  musicBrainzArtistId: string[];
+  musicBrainzReleaseArtistId: string[];
+  titleSort: string | null;
+  artistSort: string | null;
+  albumSort: string | null;
+  albumArtistSort: string | null;
+  composerSort: string | null;
+  subtitle: string | null;
+  grouping: string | null;
+  bpm: number | null;
+  initialKey: string | null;
+  isrc: string | null;
+  barcode: string | null;
+  label: string | null;
+  catalogNumber: string | null;
+  work: string | null;
+  movement: string | null;
+  movementNumber: number | null;
+  movementTotal: number | null;
+  originalReleaseDate: string | null;
+  releaseCountry: string | null;
+  copyright: string | null;
+  conductor: string[];
+  lyricist: string[];
+  arranger: string[];
+  producer: string[];
+  remixer: string[];
+  mood: string[];
+  compilation: boolean | null;
+  advisory: "explicit" | "clean" | "none" | null;
+  replayGainTrackGain: number | null;
+  replayGainAlbumGain: number | null;
+  replayGainTrackPeak: number | null;
+  replayGainAlbumPeak: number | null;
+  r128TrackGain: number | null;
+  r128AlbumGain: number | null;
+  artworkUri: string | null;
+  artwork: ArtworkInfo[];
+};
+
+/**
+ * A track split from a single file by a cue sheet, with its position in
+ * milliseconds.
+ */
+type VirtualTrack = {
+  filePath: string;
+  number: number;
+  start: number;
+  end: number | null;
+};
+
+/**
+ * Virtual tracks use the path of the file they're part of, followed by their
+ * number.
+ */
+const VIRTUAL_TRACK_PATTERN = /#track=\d+$/;
+
+function getFilePath(uri: string) {
+  return uri.replace(VIRTUAL_TRACK_PATTERN, "");
+}
+
+async function getPlaybackSource(
+  uri: string
+): Promise<{ filePath: string; section?: AudioSection }> {
+  if (!VIRTUAL_TRACK_PATTERN.test(uri)) {
+    return { filePath: uri };
+  }
+  const virtualTrack = (await invoke("get_cue_track", { uri })) as VirtualTrack;
+  return {
+    filePath: virtualTrack.filePath,
+    section: {
+      start: virtualTrack.start,
+      end: virtualTrack.end ?? undefined,
+    },
+  };
+}
 
 export type TauriPlayerData = {
   folders: Record<string, string[]>;
   showAttribution?: boolean;
+  metadataVersion?: number;
+  nativePlayback?: boolean;
 };
 
 export default function createTauriPlayer(
@@ -27,13 +171,67 @@ export default function createTauriPlayer(
       host.finishPlayback();
     },
   });
+  const nativeAudioBackend = createNativeAudioBackend({
+    onFinishedPlayback: () => {
+      host.finishPlayback();
+    },
+    onUnsupportedTrack: (uri) => {
+      const track = currentTrack;
+      if (!playingNatively || track?.uri !== uri) return;
+      playingNatively = false;
+      loadWebAudioTrack(track).catch((error) => {
+        console.error(`Error playing ${uri}:`, error);
+        host.finishPlayback();
+      });
+    },
+  });
+  // The setting only applies from the next track, so the current one keeps
+  // playing through the backend it started with. Tracks in formats the engine
+  // can't decode fall back to the webview
+  let playingNatively = false;
+  let currentTrack: Track | null = null;
   let folders = { ...initialConfig.folders };
   rescanFolders();
-  getMetadata(host.getTracks().filter((track) => !track.metadataLoaded));
+  if (initialConfig.metadataVersion !== METADATA_SCHEMA_VERSION) {
+    getMetadata(host.getTracks());
+    host.updateData({ metadataVersion: METADATA_SCHEMA_VERSION });
+  } else {
+    getMetadata(host.getTracks().filter((track) => !track.metadataLoaded));
+  }
+
+  function getAudioBackend() {
+    return playingNatively ? nativeAudioBackend : webAudioBackend;
+  }
+
+  async function loadWebAudioTrack(track: Track) {
+    const { filePath, section } = await getPlaybackSource(track.uri);
+    const file = convertFileSrc(filePath);
+    if (!file) throw new Error("File not found");
+    const actualDuration = await webAudioBackend.loadPrimaryAudioFile(
+      track.uri,
+      file,
+      host.getMuted() ? 0 : host.getVolume() / 100,
+      section
+    );
+    if (!track.duration) {
+      getMetadata([track]);
+    } else if (actualDuration != null && actualDuration != track.duration) {
+      host.updateLibraryTracks([{ ...track, duration: actualDuration }]);
+    }
+  }
 
   async function getAudioFileNames(directoryPath: string) {
     try {
-      return await invoke("get_audio_files_from_directory", { directoryPath });
+      const scan = (await invoke("get_audio_files_from_directory", {
+        directoryPath,
+      })) as AudioFilesScan;
+      if (scan.errors.length > 0) {
+        console.warn(
+          `Skipped paths while scanning ${directoryPath}:`,
+          scan.errors
+        );
+      }
+      return scan.files;
     } catch (error) {
       console.error("Error getting files:", error);
     }
@@ -61,36 +259,37 @@ export default function createTauriPlayer(
   }
 
   async function getMetadata(tracks: Track[]) {
-    function parseNumber(value: string | undefined): number | undefined {
-      const parsedValue = parseInt(value ?? "0", 10);
-      return parsedValue !== 0 ? parsedValue : undefined;
-    }
     for (let i = 0; i < tracks.length; i += 10) {
       const batch = tracks.slice(i, i + 10);
       const metadataPromises = batch.map((track) =>
         invoke("get_metadata", { filePath: track.uri })
           .then((result: unknown) => {
-            const metadata = result as Partial<Record<keyof Track, string>>;
+            const metadata = result as FileMetadata;
+            if (metadata.cueTrackUris.length > 0) {
+              splitTrack(track.uri, metadata.cueTrackUris);
+              return null;
+            }
             return {
               uri: track.uri,
               title: metadata.title || track.title,
               metadataLoaded: true,
-              duration: parseNumber(metadata.duration),
-              artist: metadata.artist && JSON.parse(metadata.artist),
-              albumArtist: metadata.albumArtist,
-              album: metadata.album,
-              genre: metadata.genre && JSON.parse(metadata.genre),
-              composer: metadata.composer && JSON.parse(metadata.composer),
-              comments: metadata.comments && JSON.parse(metadata.comments),
-              artworkUri: metadata.artworkUri,
-              year: parseNumber(metadata.year),
-              dateReleased: parseNumber(metadata.dateReleased),
-              track: parseNumber(metadata.track),
-              disc: parseNumber(metadata.disc),
-              fileSize: parseNumber(metadata.fileSize),
-              sampleRate: parseNumber(metadata.sampleRate),
-              bitRate: parseNumber(metadata.bitRate),
-              dateModified: parseNumber(metadata.dateModified),
+              duration: metadata.duration || undefined,
+              artist: metadata.artist,
+              albumArtist: metadata.albumArtist ?? undefined,
+              album: metadata.album ?? undefined,
+              genre: metadata.genre,
+              composer: metadata.composer,
+              comments: metadata.comments,
+              artworkUri: metadata.artworkUri ?? undefined,
+              year: metadata.year ?? undefined,
+              dateReleased: metadata.dateReleased ?? undefined,
+              track: metadata.track || undefined,
+              disc: metadata.disc || undefined,
+              fileSize: metadata.fileSize,
+              fileFormat: metadata.codec ?? track.fileFormat,
+              sampleRate: metadata.sampleRate ?? undefined,
+              bitRate: metadata.bitRate ?? undefined,
+              dateModified: metadata.dateModified,
             } as Track;
           })
           .catch((err) => {
@@ -125,9 +324,9 @@ export default function createTauriPlayer(
           title: fileName.split("\\").pop(),
           album: fileName.split("\\").slice(-2, -1)[0],
           dateAdded,
-          filePath: fileName,
+          filePath: getFilePath(fileName),
           fileFolder: fileName.split("\\").slice(-2, -1)[0],
-          fileFormat: fileName.split(".").pop()?.toUpperCase(),
+          fileFormat: getFilePath(fileName).split(".").pop()?.toUpperCase(),
           metadataLoaded: false,
         }) as Track
     );
@@ -135,6 +334,24 @@ export default function createTauriPlayer(
     getMetadata(tracks);
   }
 
+  // Scans only split files by sidecar cue sheets, so a file with an embedded
+  // sheet is replaced by its tracks once its metadata has been read. Folders
+  // keep listing the file itself, as that's what scans find
+  function splitTrack(uri: string, cueTrackUris: string[]) {
+    host.removeTracks([uri]);
+    addNewTracks(cueTrackUris);
+  }
+
+  // The library tracks for files found by a scan, including the tracks of
+  // files that were split by an embedded cue sheet
+  function getLibraryTracks(fileNames: string[]) {
+    const files = new Set(fileNames);
+    return host
+      .getTracks()
+      .map((track) => track.uri)
+      .filter((uri) => files.has(uri) || files.has(getFilePath(uri)));
+  }
+
   async function rescanFolders() {
     for (const folder in folders) {
       const fileNames = (await getAudioFileNames(folder)) as string[];
@@ -145,7 +362,7 @@ export default function createTauriPlayer(
         (fileName) => !folders[folder].includes(fileName)
       );
       if (removedFileNames.length > 0) {
-        host.removeTracks(removedFileNames);
+        host.removeTracks(getLibraryTracks(removedFileNames));
       }
       if (newFileNames.length > 0 || removedFileNames.length > 0) {
         folders[folder] = fileNames;
@@ -174,7 +391,7 @@ export default function createTauriPlayer(
       })
     );
     if (!confirmed) return;
-    host.removeTracks(folders[folderPath]);
+    host.removeTracks(getLibraryTracks(folders[folderPath]));
     delete (folders = { ...folders })[folderPath];
     host.updateData({ folders: folders });
   }
@@ -205,28 +422,44 @@ export default function createTauriPlayer(
     QuickStart: (props) => QuickStart({ ...props, addFolder, i18n }),
 
     async loadAndPlayTrack(track: Track) {
-      const file = convertFileSrc(track.uri);
-      if (!file) throw new Error("File not found");
-      const actualDuration = await webAudioBackend.loadPrimaryAudioFile(
-        track.uri,
-        file,
-        host.getMuted() ? 0 : host.getVolume() / 100
-      );
-      if (!track.duration) {
-        getMetadata([track]);
-      } else if (actualDuration != null && actualDuration != track.duration) {
-        host.updateLibraryTracks([{ ...track, duration: actualDuration }]);
+      currentTrack = track;
+      const usesNativePlayback =
+        (host.getData() as TauriPlayerData).nativePlayback ?? false;
+      if (usesNativePlayback !== playingNatively) {
+        getAudioBackend().pause();
+        playingNatively = usesNativePlayback;
       }
+      if (playingNatively) {
+        await nativeAudioBackend.loadPrimaryAudioFile(
+          track.uri,
+          host.getMuted() ? 0 : host.getVolume() / 100
+        );
+        if (!track.duration) {
+          getMetadata([track]);
+        }
+        return;
+      }
+      await loadWebAudioTrack(track);
     },
 
     setTrackToPreload(track: Track | null) {
       webAudioBackend.clearSecondaryAudioFile();
+      if (playingNatively) {
+        nativeAudioBackend.setNextTrack(track?.uri ?? null);
+        return;
+      }
       if (!track) {
         return;
       }
-      const file = convertFileSrc(track.uri);
-      if (!file) return;
-      webAudioBackend.loadSecondaryAudioFile(track.uri, file);
+      getPlaybackSource(track.uri)
+        .then(({ filePath, section }) => {
+          const file = convertFileSrc(filePath);
+          if (!file) return;
+          webAudioBackend.loadSecondaryAudioFile(track.uri, file, section);
+        })
+        .catch((error) => {
+          console.error(`Error preloading ${track.uri}:`, error);
+        });
     },
 
     async getTrackArtwork(artworkUri) {
@@ -239,23 +472,23 @@ export default function createTauriPlayer(
     },
 
     pause() {
-      webAudioBackend.pause();
+      getAudioBackend().pause();
     },
 
     resume() {
-      webAudioBackend.resume();
+      getAudioBackend().resume();
     },
 
     setVolume(volume: number) {
-      webAudioBackend.setVolume(volume / 100);
+      getAudioBackend().setVolume(volume / 100);
     },
 
     setMuted(muted: boolean) {
-      webAudioBackend.setVolume(muted ? 0 : host.getVolume() / 100);
+      getAudioBackend().setVolume(muted ? 0 : host.getVolume() / 100);
     },
 
     setTime(position: number) {
-      webAudioBackend.setTime(position);
+      getAudioBackend().setTime(position);
     },
 
     getCustomTrackActions: (track: Track) => {
@@ -272,6 +505,7 @@ export default function createTauriPlayer(
     dispose() {
       i18n.removeResourceBundle("en-US", "tauri-player");
       webAudioBackend?.dispose();
+      nativeAudioBackend.dispose();
     },
   };
 }